@@ -0,0 +1,443 @@
+use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}};
+
+use crate::{chip8::PROGRAM_START, instruction::{encode, Instruction}};
+
+/// Deepest chain of `:include`s allowed, so a file including itself fails instead of looping forever.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A problem with the source, pointing at the line that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+struct SourceLine {
+    file: String,
+    number: usize,
+    text: String
+}
+
+impl SourceLine {
+    fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError { file: self.file.clone(), line: self.number, message: message.into() }
+    }
+}
+
+/// What a line puts into the ROM.
+enum Item {
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Sprite(Vec<u8>)
+}
+
+/// Assembles `source` into a ROM to be loaded at 0x200. `:include` paths are relative to the working directory.
+///
+/// The syntax is the classic mnemonics printed by the disassembler (`LD V0, 0x0C`, `DRW V0, V1, 5`, ...) plus:
+/// - `name:` or `: name` to define a label
+/// - `:const NAME value` to define a constant
+/// - `:byte 1 2 0xFF` or `DB 1, 2, 0xFF` for raw bytes, and `DW 0x1234` for raw words
+/// - `:sprite "..####.." "..#..#.."` for a sprite drawn with `#` (or `1`) for on and `.` (or `0`) for off,
+///   8 or 16 pixels per row
+/// - `:include "other.asm"` to paste in another file
+/// - `;` starts a comment
+///
+/// Numbers may be written in decimal, `0x` hexadecimal or `0b` binary.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_source("<input>", source, Path::new("."), 0, &mut lines)?;
+    assemble_lines(&lines)
+}
+
+/// Assembles the file at `path`. `:include` paths are relative to the including file.
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    include(path, 0, &mut lines, None)?;
+    assemble_lines(&lines)
+}
+
+fn include(path: &Path, depth: usize, lines: &mut Vec<SourceLine>, from: Option<&SourceLine>) -> Result<(), AsmError> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        let message = format!("could not read {}: {}", path.display(), e);
+        match from {
+            Some(line) => line.error(message),
+            None => AsmError { file: path.display().to_string(), line: 0, message }
+        }
+    })?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    read_source(&path.display().to_string(), &source, base, depth, lines)
+}
+
+/// Splits a file into lines, expanding `:include`s in place.
+fn read_source(file: &str, source: &str, base: &Path, depth: usize, lines: &mut Vec<SourceLine>) -> Result<(), AsmError> {
+    for (index, text) in source.lines().enumerate() {
+        let line = SourceLine { file: file.to_string(), number: index + 1, text: strip_comment(text).trim().to_string() };
+        if let Some(rest) = line.text.strip_prefix(":include") {
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Err(line.error("too many nested includes"));
+            }
+            let name = unquote(rest.trim()).ok_or_else(|| line.error(":include expects a quoted file name"))?;
+            let path: PathBuf = base.join(name);
+            include(&path, depth + 1, lines, Some(&line))?;
+        } else if !line.text.is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(())
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn assemble_lines(lines: &[SourceLine]) -> Result<Vec<u8>, AsmError> {
+    // First pass: work out where everything goes so labels can be used before they are defined
+    let mut symbols: HashMap<String, u32> = HashMap::new();
+    let mut items: Vec<(&SourceLine, Item)> = Vec::new();
+    let mut address = PROGRAM_START as u32;
+
+    for line in lines {
+        let mut text = line.text.as_str();
+
+        // Labels, in either `name:` or `: name` form
+        loop {
+            if let Some(rest) = text.strip_prefix(": ") {
+                let (name, rest) = split_word(rest.trim_start());
+                define(&mut symbols, line, name, address)?;
+                text = rest.trim_start();
+            } else if let Some((name, rest)) = text.split_once(':').filter(|(name, _)| is_identifier(name)) {
+                define(&mut symbols, line, name, address)?;
+                text = rest.trim_start();
+            } else {
+                break;
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (word, rest) = split_word(text);
+        let item = match word.to_ascii_lowercase().as_str() {
+            ":const" => {
+                let (name, value) = split_word(rest.trim_start());
+                let value = resolve(&symbols, line, value.trim())?;
+                define(&mut symbols, line, name, value)?;
+                continue;
+            },
+            ":byte" => Item::Bytes(rest.split_whitespace().map(String::from).collect()),
+            "db" => Item::Bytes(split_operands(rest)),
+            "dw" => Item::Words(split_operands(rest)),
+            ":sprite" => Item::Sprite(parse_sprite(line, rest)?),
+            _ if word.starts_with(':') => return Err(line.error(format!("unknown directive '{}'", word))),
+            _ => Item::Instruction { mnemonic: word.to_ascii_uppercase(), operands: split_operands(rest) }
+        };
+
+        address += match &item {
+            Item::Instruction { operands, .. } if operands.iter().any(|operand| is_long(operand)) => 4,
+            Item::Instruction { .. } => 2,
+            Item::Bytes(values) => values.len() as u32,
+            Item::Words(values) => 2 * values.len() as u32,
+            Item::Sprite(bytes) => bytes.len() as u32
+        };
+        if address > 0x10000 {
+            return Err(line.error("program does not fit in memory"));
+        }
+        items.push((line, item));
+    }
+
+    // Second pass: encode with every label known
+    let mut rom = Vec::new();
+    for (line, item) in items {
+        match item {
+            Item::Instruction { mnemonic, operands } => {
+                let (instruction, long) = parse_instruction(&symbols, line, &mnemonic, &operands)?;
+                rom.extend_from_slice(&encode(&instruction).to_be_bytes());
+                if let Some(long) = long {
+                    rom.extend_from_slice(&long.to_be_bytes());
+                }
+            },
+            Item::Bytes(values) => {
+                for value in values {
+                    rom.push(fit(line, resolve(&symbols, line, &value)?, 0xFF)? as u8);
+                }
+            },
+            Item::Words(values) => {
+                for value in values {
+                    rom.extend_from_slice(&(fit(line, resolve(&symbols, line, &value)?, 0xFFFF)? as u16).to_be_bytes());
+                }
+            },
+            Item::Sprite(bytes) => rom.extend_from_slice(&bytes)
+        }
+    }
+    Ok(rom)
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], &text[index..]),
+        None => (text, "")
+    }
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(|operand| operand.trim().to_string()).collect()
+}
+
+fn is_long(operand: &str) -> bool {
+    // By byte, so a multi-byte character in the first five can't split the slice
+    operand.len() > 5 && operand.get(..5).is_some_and(|prefix| prefix.eq_ignore_ascii_case("long "))
+}
+
+fn define(symbols: &mut HashMap<String, u32>, line: &SourceLine, name: &str, value: u32) -> Result<(), AsmError> {
+    if !is_identifier(name) {
+        return Err(line.error(format!("'{}' is not a valid name", name)));
+    }
+    if register(name).is_some() {
+        return Err(line.error(format!("'{}' is a register and cannot be used as a name", name)));
+    }
+    if symbols.insert(name.to_string(), value).is_some() {
+        return Err(line.error(format!("'{}' is already defined", name)));
+    }
+    Ok(())
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = text.strip_prefix("0b").or_else(|| text.strip_prefix("0B")) {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Turns a number, label or constant into its value.
+fn resolve(symbols: &HashMap<String, u32>, line: &SourceLine, text: &str) -> Result<u32, AsmError> {
+    if text.is_empty() {
+        return Err(line.error("missing value"));
+    }
+    parse_number(text)
+        .or_else(|| symbols.get(text).copied())
+        .ok_or_else(|| line.error(format!("unknown label or constant '{}'", text)))
+}
+
+fn fit(line: &SourceLine, value: u32, max: u32) -> Result<u32, AsmError> {
+    if value > max {
+        return Err(line.error(format!("value 0x{:X} does not fit, the largest allowed is 0x{:X}", value, max)));
+    }
+    Ok(value)
+}
+
+fn register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_sprite(line: &SourceLine, text: &str) -> Result<Vec<u8>, AsmError> {
+    let mut bytes = Vec::new();
+    for row in text.split_whitespace() {
+        let row = unquote(row).ok_or_else(|| line.error(format!("sprite rows must be quoted, got {}", row)))?;
+        if row.len() != 8 && row.len() != 16 {
+            return Err(line.error(format!("sprite rows must be 8 or 16 pixels wide, got {}", row.len())));
+        }
+        let mut bits: u16 = 0;
+        for c in row.chars() {
+            bits = bits << 1 | match c {
+                '#' | '1' => 1,
+                '.' | '0' => 0,
+                _ => return Err(line.error(format!("unexpected '{}' in sprite, use # and .", c)))
+            };
+        }
+        if row.len() == 16 {
+            bytes.extend_from_slice(&bits.to_be_bytes());
+        } else {
+            bytes.push(bits as u8);
+        }
+    }
+    if bytes.is_empty() {
+        return Err(line.error(":sprite needs at least one row"));
+    }
+    Ok(bytes)
+}
+
+/// Works out which instruction a mnemonic and its operands mean. The second value is the address that
+/// follows `LD I, LONG`.
+fn parse_instruction(symbols: &HashMap<String, u32>, line: &SourceLine, mnemonic: &str, operands: &[String]) -> Result<(Instruction, Option<u16>), AsmError> {
+    let ops: Vec<&str> = operands.iter().map(String::as_str).collect();
+    let upper: Vec<String> = operands.iter().map(|operand| operand.to_ascii_uppercase()).collect();
+    let upper: Vec<&str> = upper.iter().map(String::as_str).collect();
+
+    let reg = |index: usize| register(ops[index]).ok_or_else(|| line.error(format!("expected a register, got '{}'", ops[index])));
+    let value = |index: usize, max: u32| fit(line, resolve(symbols, line, ops[index])?, max);
+    let address = |index: usize| value(index, 0xFFF).map(|value| value as u16);
+    let byte = |index: usize| value(index, 0xFF).map(|value| value as u8);
+    let nibble = |index: usize| value(index, 0xF).map(|value| value as u8);
+    let is_reg = |index: usize| register(ops[index]).is_some();
+
+    let instruction = match (mnemonic, ops.len()) {
+        ("CLS", 0) => Instruction::Clear,
+        ("RET", 0) => Instruction::Return,
+        ("SYS", 1) => Instruction::Sys { nnn: address(0)? },
+        ("JP", 1) => Instruction::Jump { nnn: address(0)? },
+        ("JP", 2) if upper[0] == "V0" => Instruction::JumpOffset { nnn: address(1)? },
+        ("CALL", 1) => Instruction::Call { nnn: address(0)? },
+        ("SE", 2) if is_reg(1) => Instruction::SkipEqReg { x: reg(0)?, y: reg(1)? },
+        ("SE", 2) => Instruction::SkipEqImm { x: reg(0)?, nn: byte(1)? },
+        ("SNE", 2) if is_reg(1) => Instruction::SkipNeReg { x: reg(0)?, y: reg(1)? },
+        ("SNE", 2) => Instruction::SkipNeImm { x: reg(0)?, nn: byte(1)? },
+        ("ADD", 2) if upper[0] == "I" => Instruction::AddIndex { x: reg(1)? },
+        ("ADD", 2) if is_reg(1) => Instruction::AddReg { x: reg(0)?, y: reg(1)? },
+        ("ADD", 2) => Instruction::AddImm { x: reg(0)?, nn: byte(1)? },
+        ("OR", 2) => Instruction::Or { x: reg(0)?, y: reg(1)? },
+        ("AND", 2) => Instruction::And { x: reg(0)?, y: reg(1)? },
+        ("XOR", 2) => Instruction::Xor { x: reg(0)?, y: reg(1)? },
+        ("SUB", 2) => Instruction::Sub { x: reg(0)?, y: reg(1)? },
+        ("SUBN", 2) => Instruction::SubReverse { x: reg(0)?, y: reg(1)? },
+        ("SHR", 1) => Instruction::ShiftRight { x: reg(0)?, y: reg(0)? },
+        ("SHR", 2) => Instruction::ShiftRight { x: reg(0)?, y: reg(1)? },
+        ("SHL", 1) => Instruction::ShiftLeft { x: reg(0)?, y: reg(0)? },
+        ("SHL", 2) => Instruction::ShiftLeft { x: reg(0)?, y: reg(1)? },
+        ("RND", 2) => Instruction::Random { x: reg(0)?, nn: byte(1)? },
+        ("DRW", 3) => Instruction::Draw { x: reg(0)?, y: reg(1)?, n: nibble(2)? },
+        ("SKP", 1) => Instruction::SkipKey { x: reg(0)? },
+        ("SKNP", 1) => Instruction::SkipNotKey { x: reg(0)? },
+        ("SCD", 1) => Instruction::ScrollDown { n: nibble(0)? },
+        ("SCU", 1) => Instruction::ScrollUp { n: nibble(0)? },
+        ("SCR", 0) => Instruction::ScrollRight,
+        ("SCL", 0) => Instruction::ScrollLeft,
+        ("EXIT", 0) => Instruction::Exit,
+        ("LOW", 0) => Instruction::LowRes,
+        ("HIGH", 0) => Instruction::HighRes,
+        ("SAVE", 2) => Instruction::SaveRange { x: reg(0)?, y: reg(1)? },
+        ("LOAD", 2) => Instruction::LoadRange { x: reg(0)?, y: reg(1)? },
+        ("PLANE", 1) => Instruction::SelectPlanes { n: nibble(0)? },
+        ("AUDIO", 0) => Instruction::LoadAudio,
+        ("PITCH", 1) => Instruction::SetPitch { x: reg(0)? },
+        ("LD", 2) => match (upper[0], upper[1]) {
+            ("I", long) if is_long(long) => {
+                let target = fit(line, resolve(symbols, line, ops[1][5..].trim())?, 0xFFFF)?;
+                return Ok((Instruction::LoadLongIndex, Some(target as u16)));
+            },
+            ("I", _) => Instruction::LoadIndex { nnn: address(1)? },
+            ("DT", _) => Instruction::SetDelay { x: reg(1)? },
+            ("ST", _) => Instruction::SetSound { x: reg(1)? },
+            ("F", _) => Instruction::LoadFont { x: reg(1)? },
+            ("HF", _) => Instruction::LoadBigFont { x: reg(1)? },
+            ("B", _) => Instruction::Bcd { x: reg(1)? },
+            ("[I]", _) => Instruction::Store { x: reg(1)? },
+            ("R", _) => Instruction::SaveFlags { x: reg(1)? },
+            (_, "DT") => Instruction::LoadDelay { x: reg(0)? },
+            (_, "K") => Instruction::WaitKey { x: reg(0)? },
+            (_, "[I]") => Instruction::Load { x: reg(0)? },
+            (_, "R") => Instruction::LoadFlags { x: reg(0)? },
+            _ if is_reg(1) => Instruction::Move { x: reg(0)?, y: reg(1)? },
+            _ => Instruction::LoadImm { x: reg(0)?, nn: byte(1)? }
+        },
+        _ => return Err(line.error(format!("unknown instruction '{}' with {} operand(s)", mnemonic, ops.len())))
+    };
+    Ok((instruction, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn labels_can_be_used_before_and_after_they_are_defined() {
+        let rom = assemble("start: JP end\n: middle\nJP middle\nend:\nJP start").unwrap();
+        assert_eq!(rom, [0x12, 0x04, 0x12, 0x02, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn constants_resolve_like_labels() {
+        let rom = assemble(":const SPEED 3\nLD V0, SPEED").unwrap();
+        assert_eq!(rom, [0x60, 0x03]);
+    }
+
+    #[test]
+    fn long_index_loads_take_four_bytes() {
+        let rom = assemble("LD I, long data\nJP data\ndata: DB 1").unwrap();
+        assert_eq!(rom, [0xF0, 0x00, 0x02, 0x06, 0x12, 0x06, 0x01]);
+    }
+
+    #[test]
+    fn long_is_case_insensitive() {
+        assert_eq!(assemble("LD I, LONG 0xFFFF").unwrap(), [0xF0, 0x00, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn multi_byte_characters_in_operands_are_an_error() {
+        assert_eq!(error("LD I, ab\u{20AC}").line, 1);
+        assert_eq!(error("LD I, abcd\u{20AC}").line, 1);
+    }
+
+    #[test]
+    fn data_directives() {
+        let rom = assemble(":byte 1 0x02 0b11\nDB 4, 5\nDW 0x1234").unwrap();
+        assert_eq!(rom, [1, 2, 3, 4, 5, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn sprites() {
+        let rom = assemble(":sprite \"#......#\" \"1010101010101010\"").unwrap();
+        assert_eq!(rom, [0x81, 0xAA, 0xAA]);
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let error = error("CLS\n\n; comment\nLD V0, 0x100");
+        assert_eq!((error.file.as_str(), error.line), ("<input>", 4));
+        assert!(error.message.contains("does not fit"), "{}", error.message);
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        assert!(error("JP nowhere").message.contains("unknown label"));
+        assert!(error("a: CLS\na: CLS").message.contains("already defined"));
+        assert!(error("V1: CLS").message.contains("register"));
+        assert!(error("FOO V0").message.contains("unknown instruction"));
+        assert!(error(":bogus").message.contains("unknown directive"));
+        assert!(error(":byte 256").message.contains("does not fit"));
+        assert!(error(":sprite \"#..\"").message.contains("8 or 16"));
+        assert!(error("ADD V0, V1, V2").message.contains("3 operand"));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::chip8::Chip8;
+
+/// What plays before a program loads a pattern of its own: half on, half off, so one square wave per loop.
+pub const SQUARE_PATTERN: [u8; 16] = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+/// Pitch of the classic beep, for programs that never load a pattern.
+pub const BEEP_HZ: f32 = 440.0;
+
+/// Samples per second XO-CHIP plays its audio pattern at: 4000 * 2^((pitch - 64) / 48). The default pitch of
+/// 64 gives 4000.
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// A 128 bit, one bit per sample pattern looped at `rate` samples per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sound {
+    pub pattern: [u8; 16],
+    pub rate: f32
+}
+
+impl Sound {
+    /// The classic beep: a 440 Hz square wave.
+    pub fn beep() -> Sound {
+        Sound { pattern: SQUARE_PATTERN, rate: BEEP_HZ * 128.0 }
+    }
+
+    /// What `chip8` would sound like while its sound timer runs: the XO-CHIP pattern and pitch once F002 has
+    /// run, and the beep before that.
+    pub fn of(chip8: &Chip8) -> Sound {
+        match chip8.audio_pattern() {
+            Some(pattern) => Sound { pattern, rate: playback_rate(chip8.pitch()) },
+            None => Sound::beep()
+        }
+    }
+}
+
+/// Turns a `Sound` into samples for an audio device. Swapping the sound keeps the position in the pattern,
+/// so a change of pitch doesn't click.
+#[derive(Clone, Debug)]
+pub struct PatternPlayer {
+    sound: Sound,
+    // Index into the pattern's 128 bits, with a fraction
+    position: f32
+}
+
+impl PatternPlayer {
+    pub fn new(sound: Sound) -> Self {
+        PatternPlayer { sound, position: 0.0 }
+    }
+
+    pub fn sound(&self) -> Sound {
+        self.sound
+    }
+
+    pub fn set_sound(&mut self, sound: Sound) {
+        self.sound = sound;
+    }
+
+    /// Fills `out` with the next samples at `output_rate` samples per second, between -`volume` and `volume`.
+    pub fn fill(&mut self, out: &mut [f32], output_rate: u32, volume: f32) {
+        let step = self.sound.rate / output_rate as f32;
+        for sample in out.iter_mut() {
+            let bit = self.position as usize;
+            let on = self.sound.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if on { volume } else { -volume };
+            self.position = (self.position + step) % 128.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= b * 1e-4
+    }
+
+    #[test]
+    fn pitch_doubles_the_rate_every_48_steps() {
+        assert_eq!(playback_rate(64), 4000.0);
+        assert!(close(playback_rate(112), 8000.0) && close(playback_rate(16), 2000.0) && close(playback_rate(160), 16000.0));
+        for pitch in 0..=(255 - 48) {
+            assert!(close(playback_rate(pitch + 48), 2.0 * playback_rate(pitch)), "pitch {}", pitch);
+        }
+    }
+
+    /// Samples of a pattern with only its first and last bits on, as +1 and -1.
+    fn play(rate: f32, output_rate: u32, samples: usize) -> Vec<f32> {
+        let mut pattern = [0; 16];
+        pattern[0] = 0x80;
+        pattern[15] = 0x01;
+        let mut out = vec![0.0; samples];
+        PatternPlayer::new(Sound { pattern, rate }).fill(&mut out, output_rate, 1.0);
+        out
+    }
+
+    #[test]
+    fn walks_the_pattern_and_wraps() {
+        // One bit per sample
+        let out = play(8000.0, 8000, 130);
+        let on: Vec<usize> = (0..out.len()).filter(|i| out[*i] > 0.0).collect();
+        assert_eq!(on, [0, 127, 128]);
+        assert!(out.iter().all(|sample| sample.abs() == 1.0));
+
+        // Half the output rate holds each bit for two samples
+        let out = play(4000.0, 8000, 258);
+        let on: Vec<usize> = (0..out.len()).filter(|i| out[*i] > 0.0).collect();
+        assert_eq!(on, [0, 1, 254, 255, 256, 257]);
+    }
+
+    #[test]
+    fn carries_on_between_fills() {
+        let whole = play(3000.0, 44100, 1000);
+        let mut player = PatternPlayer::new(Sound { pattern: [0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01], rate: 3000.0 });
+        let mut parts = vec![0.0; 1000];
+        let (first, second) = parts.split_at_mut(333);
+        player.fill(first, 44100, 1.0);
+        player.fill(second, 44100, 1.0);
+        assert_eq!(parts, whole);
+    }
+
+    #[test]
+    fn beeps_until_a_pattern_is_loaded() {
+        // LD I, 0x208, AUDIO, LD V0, 112, PITCH V0, then the pattern
+        let mut program = vec![0xA2, 0x08, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A];
+        program.extend(0x10..0x20);
+        let mut chip8 = Chip8::from_profile(&Platform::XoChip.profile());
+        chip8.load_bytes(&program).unwrap();
+        assert_eq!(Sound::of(&chip8), Sound { pattern: SQUARE_PATTERN, rate: 440.0 * 128.0 });
+
+        for _ in 0..4 {
+            chip8.step().unwrap();
+        }
+        let sound = Sound::of(&chip8);
+        assert_eq!(sound.pattern.to_vec(), (0x10..0x20).collect::<Vec<u8>>());
+        assert!(close(sound.rate, 8000.0));
+    }
+}
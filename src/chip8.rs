@@ -1,484 +1,863 @@
-use std::{fs, io::Read, path::Path, thread, time::{Duration, Instant}};
-use rand::Rng;
-use sdl2::{audio::{AudioCallback, AudioDevice, AudioSpecDesired}, event::Event, keyboard::Scancode, pixels::Color, rect::Rect, render::Canvas, video::Window, EventPump};
-
-use crate::font::write_font;
-
+use std::{fs, io, ops::Range, path::Path};
+
+use crate::{error::Chip8Error, font::{write_font, Font, BIG_FONT_ADDRESS, FONT_ADDRESS}, instruction::{decode, encode, Instruction, InstructionSet}, platform::{Platform, Profile}, quirks::Quirks, rng::{Rng, RngKind}, trace::{TraceRecord, Tracer}};
+
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+/// SUPER-CHIP's high resolution mode, switched on by 00FF.
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const MEMORY_SIZE: usize = 4096;
+/// XO-CHIP's 64 KiB address space, the most F000 NNNN can reach.
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+/// Programs are loaded here, and execution starts here.
+pub const PROGRAM_START: u16 = 0x200;
+/// Deepest level of nested subroutine calls.
+pub const STACK_SIZE: usize = 16;
+/// Emulated frames per second. The timers count down once a frame, whatever rate the host redraws at.
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+/// Whether an instruction reads or writes memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write
+}
 
-// CONFIG
-const DARK_COLOR: Color = Color::RGB(188, 107, 184);
-const LIGHT_COLOR: Color = Color::RGB(141, 173, 104);
+/// The block of memory an instruction touches through the index register. The end of 64 KiB memory is
+/// 0x10000, one past the last u16 address, so the bounds are kept as usize.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub start: usize,
+    pub len: usize
+}
 
-const LOOPS_PER_SECOND: u128 = 240;
-const INSTRUCTIONS_PER_SECOND: u128 = 2600; 
+impl MemoryAccess {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
 
-const OLD_SHIFT_FUNCTIONALITY: bool = true;
-const B_JUMP_REG_OFFSET: bool = false;
-const MOVABLE_INDEX_ON_SAVE_LOAD: bool = false;
-const WRAP_SPRITES: bool = true;
+/// A copy of everything that makes up the machine's state, for save states and rewinding.
+/// Keypad state and the tracer are left out: they belong to whoever is driving the `Chip8`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The platform the machine was set up for, which decides its memory size and instruction set
+    pub platform: Option<Platform>,
+    pub pixels: Vec<Vec<u8>>,
+    pub memory: Vec<u8>,
+    pub registers: [u8; 16],
+    pub register_i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub quirks: Quirks,
+    pub vblank: bool,
+    pub rng: Rng,
+    pub flags: [u8; 16],
+    pub planes: u8,
+    pub audio_pattern: Option<[u8; 16]>,
+    pub pitch: u8
+}
 
+/// The interpreter itself: memory, registers, timers, framebuffer and keypad state.
+/// It knows nothing about windows or sound devices, so it can be driven by any frontend
+/// (or by nothing at all when running headless).
 pub struct Chip8 {
-    pixels: Vec<Vec<bool>>,
-    canvas: Canvas<Window>,
-    audio_device: AudioDevice<SquareWave>,
-    pixel_width: u32,
-    events: EventPump,
-    memory: [u8; 4096],
+    // Each pixel is a bitmask of the XO-CHIP bitplanes it is on in. Without XO-CHIP only plane 1 is used
+    pixels: Vec<Vec<u8>>,
+    // MEMORY_SIZE, or XO_MEMORY_SIZE for XO-CHIP
+    memory: Vec<u8>,
     registers: [u8; 16],
     register_i: u16,
     pc: u16,
     stack: Vec<u16>,
     delay_timer: u8,
     sound_timer: u8,
-    quit: bool
+    keys: [bool; 16],
+    quirks: Quirks,
+    platform: Option<Platform>,
+    // Anything newer is an unknown opcode
+    instruction_set: InstructionSet,
+    // Set at the start of every frame and cleared by the first draw, for the display wait quirk
+    vblank: bool,
+    rng: Rng,
+    // SUPER-CHIP's RPL user flags, for FX75/FX85
+    flags: [u8; 16],
+    // Bitplanes selected by FN01 for drawing, clearing and scrolling
+    planes: u8,
+    // XO-CHIP's sound, loaded by F002. Until then the sound timer plays the classic beep
+    audio_pattern: Option<[u8; 16]>,
+    // Set by FX3A. See `audio::playback_rate`
+    pitch: u8,
+    // Set by 00FD. The program counter stays on the exit instruction from then on
+    exited: bool,
+    tracer: Option<Tracer>
 }
 
 impl Chip8 {
 
-    pub fn start(&mut self) {
-        let mut quit = false;
-        while !quit {
-            quit = self.single_loop();
+    /// Executes the instruction at the program counter. If it faults, the program counter is left
+    /// pointing at the faulting instruction so it can be inspected.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let opcode = self.fetch()?;
+        if self.tracer.is_some() {
+            let record = TraceRecord::new(self, opcode);
+            if let Some(tracer) = &mut self.tracer {
+                // A trace that can't be written shouldn't stop the program
+                let _ = tracer.trace(&record);
+            }
         }
-    }
-
-    fn single_instruction(&mut self) {
-        self.check_keys_pressed();
-        let instruction: u16;
-        instruction = u16::from(self.memory[self.pc as usize]) << 8 | u16::from(self.memory[(self.pc + 1) as usize]) << 0;
-        println!("Instruction: {:04X}, PC: {:012X}", instruction, self.pc);
-
-        self.pc += 2;
 
-        if self.pc == (0x3BC + 0x002) {
-            // Spot for easy breakpoint based on pc addresses
-            println!("breakpoint");
+        self.execute(decode(opcode))
+    }
 
+    /// Reads the opcode at the program counter without executing it.
+    pub fn fetch(&self) -> Result<u16, Chip8Error> {
+        let address = self.pc as usize;
+        if address + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfRange { pc: self.pc });
         }
+        Ok(u16::from(self.memory[address]) << 8 | u16::from(self.memory[address + 1]))
+    }
 
-        let first_nibble = (instruction & 0xF000) >> 12;
+    /// Executes `instruction` as if it had been fetched from the program counter.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        let address = self.pc;
+        self.pc = self.pc.wrapping_add(instruction.size());
 
-        match first_nibble {
-            0x0 => {
-                if instruction == 0x00E0 { self.clear(); }
-                if instruction == 0x00EE { self.pc = self.stack.pop().unwrap(); }
-            },
-            0x1 => { 
+        let result = self.execute_at(instruction, address);
+        if result.is_err() {
+            self.pc = address;
+        }
+        result
+    }
 
-                let jump_location = 0x0FFF & instruction;
-                self.pc = jump_location;
+    fn execute_at(&mut self, instruction: Instruction, address: u16) -> Result<(), Chip8Error> {
+        // DXY0 is still a sprite without SUPER-CHIP, just an empty one
+        if !self.supports(instruction) && !matches!(instruction, Instruction::Draw { .. }) {
+            return Err(Chip8Error::UnknownOpcode { opcode: encode(&instruction), address });
+        }
+        match instruction {
+            Instruction::Sys { .. } => {},
+            Instruction::Clear => { self.clear(); },
+            Instruction::Return => {
+                self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow { address })?;
             },
-            0x2 => { 
-                self.stack.push(self.pc);
-                let jump_location = 0x0FFF & instruction;
-                self.pc = jump_location;
-            },
-            0x3 => { 
-                let reg = ((instruction & 0x0F00) >> 8) as usize;
-                let val = (instruction & 0x00FF) as u8;
-                if self.registers[reg] == val { self.pc += 2; }
-            },
-            0x4 => { 
-                let reg = ((instruction & 0x0F00) >> 8) as usize;
-                let val = (instruction & 0x00FF) as u8;
-                if self.registers[reg] != val { self.pc += 2; }
-            },
-            0x5 => { 
-                let reg_x = ((instruction & 0x0F00) >> 8) as usize;
-                let reg_y = ((instruction & 0x00F0) >> 4) as usize;
-                if self.registers[reg_x] == self.registers[reg_y] { self.pc += 2; }
-            },
-            0x6 => {
-                let second_nibble = (instruction & 0x0F00) >> 8;
-                let value = instruction & 0x00FF;
-                self.registers[second_nibble as usize] = value as u8;
-            },
-            0x7 => { 
-                let second_nibble = (instruction & 0x0F00) >> 8;
-                let value = instruction & 0x00FF;
-                self.registers[second_nibble as usize] = self.registers[second_nibble as usize].wrapping_add(value as u8);
-            },
-            0x8 => {
-                let last_nibble = instruction & 0x000F;
-                let x = ((instruction & 0x0F00) >> 8) as usize;
-                let y = ((instruction & 0x00F0) >> 4) as usize;
-                match last_nibble {
-                    0x0000 => { self.registers[x] = self.registers[y]; },
-                    0x0001 => { self.registers[x] = self.registers[x] | self.registers[y]; },
-                    0x0002 => { self.registers[x] = self.registers[x] & self.registers[y]; },
-                    0x0003 => { self.registers[x] = self.registers[x] ^ self.registers[y]; },
-                    0x0004 => { 
-                        let flag = if self.registers[x].checked_add(self.registers[y]) == None { 1 } else { 0 };
-                        self.registers[x] = self.registers[x].wrapping_add(self.registers[y]); 
-                        self.registers[0xF] = flag;
-                    },
-                    0x0005 => {
-                        let flag = if self.registers[x] >= self.registers[y] { 1 } else { 0 };
-                        self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]); 
-                        self.registers[0xF] = flag;
-                    },
-                    0x0006 => {
-                        let flag = if (0b00000001 & self.registers[x]) > 0 { 1 } else { 0 };
-                        if OLD_SHIFT_FUNCTIONALITY { self.registers[x] = self.registers[y]; }
-                        self.registers[x] >>= 1;
-                        self.registers[0xF] = flag;
-                    },
-                    0x0007 => {
-                        let flag = if self.registers[y] >= self.registers[x] { 1 } else { 0 };
-                        self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]); 
-                        self.registers[0xF] = flag;
-                    },
-                    0x000E => {
-                        if OLD_SHIFT_FUNCTIONALITY { self.registers[x] = self.registers[y]; }
-                        let flag = if (0b10000000 & self.registers[x]) > 0 { 1 } else { 0 };
-                        self.registers[x] <<= 1;
-                        self.registers[0xF] = flag;
-                    },
-                    _ => { panic!("There should absolutely be a last nibble on an 0x8XYN instruction")}
+            Instruction::Jump { nnn } => { self.pc = nnn; },
+            Instruction::Call { nnn } => {
+                if self.stack.len() >= STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow { address });
                 }
+                self.stack.push(self.pc);
+                self.pc = nnn;
             },
-            0x9 => { 
-                let reg_x = ((instruction & 0x0F00) >> 8) as usize;
-                let reg_y = ((instruction & 0x00F0) >> 4) as usize;
-                if self.registers[reg_x] != self.registers[reg_y] { self.pc += 2; }
+            Instruction::SkipEqImm { x, nn } => {
+                if self.registers[x as usize] == nn { self.skip(); }
             },
-            0xA => { 
-
-                self.register_i = instruction & 0x0FFF; 
+            Instruction::SkipNeImm { x, nn } => {
+                if self.registers[x as usize] != nn { self.skip(); }
+            },
+            Instruction::SkipEqReg { x, y } => {
+                if self.registers[x as usize] == self.registers[y as usize] { self.skip(); }
+            },
+            Instruction::LoadImm { x, nn } => { self.registers[x as usize] = nn; },
+            Instruction::AddImm { x, nn } => {
+                self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn);
             },
-            0xB => {
+            Instruction::Move { x, y } => { self.registers[x as usize] = self.registers[y as usize]; },
+            Instruction::Or { x, y } => {
+                self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset { self.registers[0xF] = 0; }
+            },
+            Instruction::And { x, y } => {
+                self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset { self.registers[0xF] = 0; }
+            },
+            Instruction::Xor { x, y } => {
+                self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset { self.registers[0xF] = 0; }
+            },
+            Instruction::AddReg { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let flag = if self.registers[x].checked_add(self.registers[y]).is_none() { 1 } else { 0 };
+                self.registers[x] = self.registers[x].wrapping_add(self.registers[y]);
+                self.registers[0xF] = flag;
+            },
+            Instruction::Sub { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let flag = if self.registers[x] >= self.registers[y] { 1 } else { 0 };
+                self.registers[x] = self.registers[x].wrapping_sub(self.registers[y]);
+                self.registers[0xF] = flag;
+            },
+            Instruction::ShiftRight { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                if self.quirks.shift_uses_vy { self.registers[x] = self.registers[y]; }
+                let flag = if (0b00000001 & self.registers[x]) > 0 { 1 } else { 0 };
+                self.registers[x] >>= 1;
+                self.registers[0xF] = flag;
+            },
+            Instruction::SubReverse { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let flag = if self.registers[y] >= self.registers[x] { 1 } else { 0 };
+                self.registers[x] = self.registers[y].wrapping_sub(self.registers[x]);
+                self.registers[0xF] = flag;
+            },
+            Instruction::ShiftLeft { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                if self.quirks.shift_uses_vy { self.registers[x] = self.registers[y]; }
+                let flag = if (0b10000000 & self.registers[x]) > 0 { 1 } else { 0 };
+                self.registers[x] <<= 1;
+                self.registers[0xF] = flag;
+            },
+            Instruction::SkipNeReg { x, y } => {
+                if self.registers[x as usize] != self.registers[y as usize] { self.skip(); }
+            },
+            Instruction::LoadIndex { nnn } => { self.register_i = nnn; },
+            Instruction::JumpOffset { nnn } => {
                 let mut offset = self.registers[0];
-                if B_JUMP_REG_OFFSET {
-                    offset = self.registers[((0x0F00 & instruction) >> 8) as usize];
+                if self.quirks.jump_uses_vx {
+                    offset = self.registers[(nnn >> 8) as usize];
                 }
 
-                self.pc = offset as u16 + (0x0FFF & instruction);
+                self.pc = offset as u16 + nnn;
             },
-            0xC => {
-                let reg = ((0x0F00 & instruction) >> 8) as usize;
-                let nn = 0x00FF & instruction;
-                let num = rand::thread_rng().gen_range(0..0xFF);
-
-                self.registers[reg] = (nn & num) as u8;
-
+            Instruction::Random { x, nn } => {
+                let num: u8 = self.rng.next_byte();
+                self.registers[x as usize] = nn & num;
             },
-            0xD => { // Display instruction
-                let x_r = (0x0F00 & instruction) >> 8; 
-                let y_r = (0x00F0 & instruction) >> 4;
-                let n = (0x000F & instruction) >> 0;
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        self.pc = address;
+                        return Ok(());
+                    }
+                    self.vblank = false;
+                }
 
-                let x = self.registers[x_r as usize];
-                let y = self.registers[y_r as usize];
+                let x = self.registers[x as usize];
+                let y = self.registers[y as usize];
 
-                self.draw_sprite(self.register_i, n as u8, x, y);
+                self.check_memory_range(self.register_i, self.sprite_size(n) * self.planes.count_ones() as usize, address)?;
+                self.draw_sprite(self.register_i, n, x, y);
             },
-            0xE => { // Skip if key instructions
-                let keys_pressed = self.check_keys_pressed();
-                let key_reg = ((0x0F00 & instruction) >> 8) as usize;
-                let key = self.registers[key_reg];
-                let which = 0x00FF & instruction;
-                //println!("{:?}", keys_pressed);
-                if which == 0x009E {
-
-                    if keys_pressed[key as usize] {
-                        self.pc += 2;
-                    }
-                } else if which == 0x00A1 {
-                    if !keys_pressed[key as usize] {
-                        self.pc += 2;
-                    }
+            Instruction::SkipKey { x } => {
+                // Only the lower nibble selects a key
+                if self.keys[(self.registers[x as usize] & 0x0F) as usize] { self.skip(); }
+            },
+            Instruction::SkipNotKey { x } => {
+                if !self.keys[(self.registers[x as usize] & 0x0F) as usize] { self.skip(); }
+            },
+            Instruction::LoadDelay { x } => { self.registers[x as usize] = self.delay_timer; },
+            Instruction::WaitKey { x } => { // Repeat until a key is pressed
+                match self.keys.iter().position(|key| *key) {
+                    Some(key_pressed) => { self.registers[x as usize] = key_pressed as u8; },
+                    None => { self.pc = address; }
                 }
             },
-            0xF => {
-                let second_half = 0x00FF & instruction;
-                let reg = ((0x0F00 & instruction) >> 8) as usize;
-                match second_half {
-                    0x07 => { self.registers[reg] = self.delay_timer; },
-                    0x0A => { // Repeat until a key is pressed
-                        
-                        let keys_pressed = self.check_keys_pressed();
-                        let mut key_pressed = 1000;
-                        for (i, key) in keys_pressed.as_ref().iter().enumerate() {
-                            if *key {
-                                key_pressed = i;
-                                break;
-                            }
-                        }
-
-                        if key_pressed >= 1000 { 
-                            self.pc -= 2; 
-                        } else {
-                            self.registers[reg] = key_pressed as u8;
-                        }
-                    },
-                    0x15 => { self.delay_timer = self.registers[reg]; }
-                    0x18 => { self.sound_timer = self.registers[reg]; }
-                    0x1E => { 
-                        if self.register_i + self.registers[reg] as u16 >= 0x1000 { self.registers[0xF] = 1; }
-                        self.register_i += self.registers[reg] as u16;
-                    },
-                    0x29 => {
-                        let character = self.registers[reg] & 0x0F;
-                        // 0x050 is the first address for the font, and each character is 5 bytes long.
-                        // See font.rs
-                        self.register_i = 0x050 + (5 * character) as u16;
-
-                    },
-                    0x33 => {
-                        let num = self.registers[reg];
-                        self.memory[self.register_i as usize] = num / 100;
-                        self.memory[(self.register_i + 1) as usize] = (num / 10) % 10;
-                        self.memory[(self.register_i + 2) as usize] = num % 10;
-                    }, 
-                    0x55 => { 
-
-                        for num in 0..=reg { 
-                            self.memory[self.register_i as usize + num] = self.registers[num]; 
-                            if MOVABLE_INDEX_ON_SAVE_LOAD { self.register_i += 1; }
-                        }
-                    },
-                    0x65 => { 
-                        for num in 0..=reg { 
-                            self.registers[num] = self.memory[self.register_i as usize + num]; 
-                            if MOVABLE_INDEX_ON_SAVE_LOAD { self.register_i += 1; }
-                        }
-                    }
-                    _ => { panic!("There was an error with an 0xF type instruction!"); }
+            Instruction::SetDelay { x } => { self.delay_timer = self.registers[x as usize]; },
+            Instruction::SetSound { x } => { self.sound_timer = self.registers[x as usize]; },
+            Instruction::AddIndex { x } => {
+                let value = self.registers[x as usize] as u16;
+                // In usize, since I can be anywhere up to 0xFFFF with XO-CHIP's memory
+                if self.quirks.index_overflow_sets_vf && self.register_i as usize + value as usize >= self.memory.len() {
+                    self.registers[0xF] = 1;
+                }
+                self.register_i = self.register_i.wrapping_add(value);
+            },
+            Instruction::LoadFont { x } => {
+                let character = self.registers[x as usize] & 0x0F;
+                // Each character is 5 bytes long. See font.rs
+                self.register_i = FONT_ADDRESS + (5 * character) as u16;
+            },
+            Instruction::Bcd { x } => {
+                self.check_memory_range(self.register_i, 3, address)?;
+                let num = self.registers[x as usize];
+                let i = self.register_i as usize;
+                self.memory[i] = num / 100;
+                self.memory[i + 1] = (num / 10) % 10;
+                self.memory[i + 2] = num % 10;
+            },
+            Instruction::Store { x } => {
+                let count = x as usize + 1;
+                self.check_memory_range(self.register_i, count, address)?;
+                let i = self.register_i as usize;
+                self.memory[i..i + count].copy_from_slice(&self.registers[..count]);
+                if self.quirks.index_increments { self.register_i = self.register_i.wrapping_add(count as u16); }
+            },
+            Instruction::Load { x } => {
+                let count = x as usize + 1;
+                self.check_memory_range(self.register_i, count, address)?;
+                let i = self.register_i as usize;
+                self.registers[..count].copy_from_slice(&self.memory[i..i + count]);
+                if self.quirks.index_increments { self.register_i = self.register_i.wrapping_add(count as u16); }
+            },
+            Instruction::ScrollDown { n } => { self.scroll(0, n as isize); },
+            Instruction::ScrollUp { n } => { self.scroll(0, -(n as isize)); },
+            Instruction::ScrollRight => { self.scroll(4, 0); },
+            Instruction::ScrollLeft => { self.scroll(-4, 0); },
+            Instruction::Exit => {
+                self.exited = true;
+                self.pc = address;
+            },
+            Instruction::LowRes => { self.pixels = vec![vec![0; SCREEN_WIDTH]; SCREEN_HEIGHT]; },
+            Instruction::HighRes => { self.pixels = vec![vec![0; HIRES_WIDTH]; HIRES_HEIGHT]; },
+            Instruction::LoadBigFont { x } => {
+                let character = self.registers[x as usize] & 0x0F;
+                self.register_i = BIG_FONT_ADDRESS + (10 * character) as u16;
+            },
+            Instruction::SaveFlags { x } => {
+                let count = x as usize + 1;
+                self.flags[..count].copy_from_slice(&self.registers[..count]);
+            },
+            Instruction::LoadFlags { x } => {
+                let count = x as usize + 1;
+                self.registers[..count].copy_from_slice(&self.flags[..count]);
+            },
+            Instruction::SaveRange { x, y } => {
+                let registers = register_range(x, y);
+                self.check_memory_range(self.register_i, registers.len(), address)?;
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.memory[self.register_i as usize + offset] = self.registers[register];
+                }
+            },
+            Instruction::LoadRange { x, y } => {
+                let registers = register_range(x, y);
+                self.check_memory_range(self.register_i, registers.len(), address)?;
+                for (offset, register) in registers.into_iter().enumerate() {
+                    self.registers[register] = self.memory[self.register_i as usize + offset];
                 }
             },
-            _ => { panic!("The instructions were decoded or written wrong."); }
+            Instruction::LoadLongIndex => {
+                let operand = address.wrapping_add(2);
+                self.check_memory_range(operand, 2, address)?;
+                self.register_i = u16::from(self.memory[operand as usize]) << 8 | u16::from(self.memory[operand as usize + 1]);
+            },
+            Instruction::SelectPlanes { n } => { self.planes = n & 0b11; },
+            Instruction::LoadAudio => {
+                self.check_memory_range(self.register_i, 16, address)?;
+                let i = self.register_i as usize;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[i..i + 16]);
+                self.audio_pattern = Some(pattern);
+            },
+            Instruction::SetPitch { x } => { self.pitch = self.registers[x as usize]; },
+            Instruction::Unknown(opcode) => { return Err(Chip8Error::UnknownOpcode { opcode, address }); }
         }
-    } 
+        Ok(())
+    }
 
-    fn single_loop(&mut self) -> bool {
-        let loop_start = Instant::now();
+    /// The memory `instruction` would read or write if it were executed now, clipped to the end of memory.
+    pub fn memory_access(&self, instruction: Instruction) -> Option<MemoryAccess> {
+        if !self.supports(instruction) && !matches!(instruction, Instruction::Draw { .. }) {
+            return None;
+        }
+        let (kind, len) = match instruction {
+            Instruction::Draw { n, .. } => (AccessKind::Read, self.sprite_size(n) * self.planes.count_ones() as usize),
+            Instruction::SaveRange { x, y } => (AccessKind::Write, register_range(x, y).len()),
+            Instruction::LoadRange { x, y } => (AccessKind::Read, register_range(x, y).len()),
+            Instruction::LoadAudio => (AccessKind::Read, 16),
+            Instruction::Bcd { .. } => (AccessKind::Write, 3),
+            Instruction::Store { x } => (AccessKind::Write, x as usize + 1),
+            Instruction::Load { x } => (AccessKind::Read, x as usize + 1),
+            _ => return None
+        };
+        let start = (self.register_i as usize).min(self.memory.len());
+        let len = len.min(self.memory.len() - start);
+        Some(MemoryAccess { kind, start, len })
+    }
 
-        self.handle_delay_timer();
-        self.handle_sound_timer();
+    /// Makes sure the `len` bytes from `start` are inside memory before the instruction at `address` touches them.
+    fn check_memory_range(&self, start: u16, len: usize, address: u16) -> Result<(), Chip8Error> {
+        let end = start as usize + len;
+        if end > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfRange { address, target: end - 1 });
+        }
+        Ok(())
+    }
 
-        for _ in 0..(INSTRUCTIONS_PER_SECOND / LOOPS_PER_SECOND) {
-            self.single_instruction();
+    /// Counts both timers down by one and starts a new frame. Frontends call this `FRAMES_PER_SECOND` times a second.
+    pub fn tick_timers(&mut self) {
+        self.vblank = true;
+        self.rng.tick();
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
         }
-        self.display();
-
-        let one_loop_nano: u128 = 1_000_000_000 / LOOPS_PER_SECOND;
-        let loop_length: u128 = loop_start.elapsed().as_nanos();
-        let time_to_wait = if one_loop_nano > loop_length { one_loop_nano - loop_length } else { 0 };
-        thread::sleep(Duration::from_nanos(time_to_wait as u64));
-        return self.quit;
-    }
-
-    fn check_keys_pressed(&mut self) -> [bool; 16] {
-        let mut keys_pressed: [bool; 16] = [false; 16];
-        let mut quit = false;
-
-        for scancode in self.events.keyboard_state().pressed_scancodes() {
-            match scancode {
-                Scancode::Num1 => { keys_pressed[0x1] = true; },
-                Scancode::Num2 => { keys_pressed[0x2] = true; },
-                Scancode::Num3 => {keys_pressed[0x3] = true;  },
-                Scancode::Num4 => { keys_pressed[0xC] = true; },
-                Scancode::Q => { keys_pressed[0x4] = true; },
-                Scancode::W => { keys_pressed[0x5] = true; },
-                Scancode::E => { keys_pressed[0x6] = true; },
-                Scancode::R => { keys_pressed[0xD] = true; },
-                Scancode::A => { keys_pressed[0x7] = true; },
-                Scancode::S => { keys_pressed[0x8] = true; },
-                Scancode::D => { keys_pressed[0x9] = true; },
-                Scancode::F => { keys_pressed[0xE] = true; },
-                Scancode::Z => { keys_pressed[0xA] = true; },
-                Scancode::X => { keys_pressed[0x0] = true; },
-                Scancode::C => { keys_pressed[0xB] = true; },
-                Scancode::V => { keys_pressed[0xF] = true; },
-                _ => {}
-            }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
         }
+    }
 
-        for event in self.events.poll_iter() {
-            match event {
-                Event::Quit { .. } => { quit = true; }
-                _ => {}
+    /// Runs one frame: the timers count down once and then `instructions` instructions are executed,
+    /// or fewer if the program exits.
+    pub fn run_frame(&mut self, instructions: u32) -> Result<(), Chip8Error> {
+        self.tick_timers();
+        for _ in 0..instructions {
+            if self.exited {
+                break;
             }
+            self.step()?;
         }
-
-        if !self.quit { self.quit = quit; }
-
-        return keys_pressed;
+        Ok(())
     }
 
-    pub fn new() -> Self {
+    /// Whether the program has stopped itself with 00FD.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
 
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let audio_subsystem = sdl_context.audio().unwrap();
-        let event_pump = sdl_context.event_pump().unwrap();
+    /// Whether the screen is in SUPER-CHIP's 128x64 mode.
+    pub fn is_hires(&self) -> bool {
+        self.pixels[0].len() == HIRES_WIDTH
+    }
 
-        let window = video_subsystem.window("Chip-8 Emulator", 64 * 16, 32 * 16)
-            .position_centered()
-            .build()
-            .unwrap();
+    /// Replaces the whole keypad state. Index `n` is key `n` on the hex keypad.
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+    }
 
-        let mut canvas = window.into_canvas().build().unwrap();
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keys[(key & 0x0F) as usize] = pressed;
+    }
 
-        canvas.set_draw_color(DARK_COLOR);
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_font(quirks, Font::Chip48)
+    }
 
-        let size = canvas.window().size(); 
-        let pixel_width = size.0 / 64;
-        let mut pixels = Vec::new();
-        // Row
-        for i in 0..32 {
-            pixels.push(Vec::new());
-            // Column
-            for _ in 0..64 {
-                pixels[i].push(false);
-            }
-        }
+    /// Sets up the quirks, font, memory size and instruction set of `profile` in one go.
+    pub fn from_profile(profile: &Profile) -> Self {
+        let mut chip8 = Self::with_font(profile.quirks, profile.font);
+        chip8.memory.resize(profile.memory_size, 0);
+        chip8.platform = profile.platform;
+        chip8.instruction_set = profile.instruction_set;
+        chip8
+    }
 
-        let desired_spec = AudioSpecDesired {
-            freq: Some(07640),
-            channels: Some(1),
-            samples: None
-        };
+    fn with_font(quirks: Quirks, font: Font) -> Self {
 
-        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-            SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
-                phase: 0.0,
-                volume: 0.05
-            }
-        }).unwrap();
+        // Indexed [row][column]
+        let pixels = vec![vec![0; SCREEN_WIDTH]; SCREEN_HEIGHT];
 
-        let mut memory: [u8; 4096] = [0; 4096];
+        let mut memory = vec![0; MEMORY_SIZE];
 
-        write_font(&mut memory);
-        //println!("{:?}", memory);
+        write_font(&mut memory, font);
         let registers: [u8; 16] = [0; 16];
 
         Chip8 {
-            canvas,
             pixels,
-            audio_device: device,
-            pixel_width,
-            events: event_pump,
             memory,
             registers,
             delay_timer: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             sound_timer: 0,
             register_i: 0x0,
             stack: Vec::new(),
-            quit: false
+            keys: [false; 16],
+            quirks,
+            platform: None,
+            instruction_set: InstructionSet::XoChip,
+            vblank: false,
+            rng: Rng::from_entropy(RngKind::Modern),
+            flags: [0; 16],
+            planes: 1,
+            audio_pattern: None,
+            pitch: 64,
+            exited: false,
+            tracer: None
         }
     }
 
+    /// Draws `bytes` rows of 8 pixels from `i`, or with 0 bytes and SUPER-CHIP a 16x16 sprite of two bytes per
+    /// row, and sets VF to report collisions. With more than one bitplane selected, the sprite for each plane
+    /// follows the one before it in memory.
     pub fn draw_sprite(&mut self, i: u16, bytes: u8, offset_x: u8, offset_y: u8) {
-        self.registers[0xF] = 0;
-        let offset_x = offset_x % 64;
-        let offset_y = offset_y % 32;
-        for byte_num in 0..bytes { // Each byte is a horizontal row in the sprite
-            let byte: u8;
-            byte = self.memory[(i + byte_num as u16) as usize];
-            for bit in 0..8 { // Each row of the sprite can be up to 8 columns long
-                let mask = 1 << 7 - bit;
-                let bit_set = (mask & byte) > 0;
-                if bit_set {
-                    let mut y_coord = (offset_y + byte_num as u8) as usize;
-                    let mut x_coord = (offset_x + bit) as usize;
-                    if (x_coord >= 64 || y_coord >= 32) && !WRAP_SPRITES { 
-                        continue; 
-                    } else {
-                        x_coord %= 64;
-                        y_coord %= 32;
+        let (width, height) = (self.pixels[0].len(), self.pixels.len());
+        let (sprite_width, rows) = if bytes == 0 && self.instruction_set >= InstructionSet::SuperChip { (16, 16) } else { (8, bytes as usize) };
+        let offset_x = offset_x as usize % width;
+        let offset_y = offset_y as usize % height;
+        let mut collided = vec![false; rows];
+        let mut clipped_rows = 0;
+        let mut start = i as usize;
+        for plane in [1, 2].into_iter().filter(|plane| self.planes & plane != 0) {
+            clipped_rows = 0;
+            for (row, row_collided) in collided.iter_mut().enumerate() { // Each row is one byte, or two for 16 wide sprites
+                let bytes = &self.memory[start..start + sprite_width / 8];
+                start += sprite_width / 8;
+                let mut y_coord = offset_y + row;
+                if y_coord >= height {
+                    if !self.quirks.wrap_sprites {
+                        clipped_rows += 1;
+                        continue;
+                    }
+                    y_coord %= height;
+                }
+                let bits = bytes.iter().fold(0u16, |bits, byte| bits << 8 | *byte as u16);
+                for column in 0..sprite_width {
+                    if bits & (1 << (sprite_width - 1 - column)) == 0 {
+                        continue;
+                    }
+                    let mut x_coord = offset_x + column;
+                    if x_coord >= width {
+                        if !self.quirks.wrap_sprites {
+                            continue;
+                        }
+                        x_coord %= width;
                     }
-                    if self.pixels[y_coord][x_coord] { self.registers[0xF] = 1; }
-                    self.pixels[y_coord][x_coord] = !self.pixels[y_coord][x_coord];
+                    if self.pixels[y_coord][x_coord] & plane != 0 { *row_collided = true; }
+                    self.pixels[y_coord][x_coord] ^= plane;
                 }
             }
         }
+        let collided_rows = collided.iter().filter(|collided| **collided).count() as u8;
+        self.registers[0xF] = if self.quirks.collision_counts_rows && self.is_hires() {
+            collided_rows + clipped_rows
+        } else {
+            (collided_rows > 0) as u8
+        };
     }
 
+    /// Clears the selected bitplanes.
     pub fn clear(&mut self) {
         for row in self.pixels.iter_mut() {
             for pixel in row.iter_mut() {
-                *pixel = false;
+                *pixel &= !self.planes;
             }
         }
     }
 
-    pub fn display(&mut self) {
-        
-        let black = LIGHT_COLOR;
-        let white = DARK_COLOR;
-
-        for (y_index ,row) in self.pixels.iter().enumerate() {
-            for (x_index, pixel) in row.iter().enumerate() {
-                let y = (y_index * self.pixel_width as usize) as i32;
-                let x = (x_index * self.pixel_width as usize) as i32;
-                let rect = Rect::new(x, y, self.pixel_width, self.pixel_width);
-
-                if *pixel {
-                    self.canvas.set_draw_color(white);
+    /// Moves the selected bitplanes `dx` pixels right and `dy` pixels down. Pixels moved in from off screen are off.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let old = self.pixels.clone();
+        let (width, height) = (old[0].len() as isize, old.len() as isize);
+        for (y, row) in self.pixels.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+                let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    old[from_y as usize][from_x as usize]
                 } else {
-                    self.canvas.set_draw_color(black);
-                }
-
-                let _ = self.canvas.fill_rect(rect);
+                    0
+                };
+                *pixel = (*pixel & !self.planes) | (moved & self.planes);
             }
         }
+    }
+
+    /// Moves the program counter past the next instruction, which for F000 NNNN is 4 bytes.
+    fn skip(&mut self) {
+        let next = self.fetch().map(decode).unwrap_or(Instruction::Unknown(0));
+        // F000 is only 4 bytes long where it is an instruction at all
+        let size = if self.supports(next) { next.size() } else { 2 };
+        self.pc = self.pc.wrapping_add(size);
+    }
+
+    /// Bytes in a sprite of `n` rows, for one bitplane. Before SUPER-CHIP, DXY0 has no rows rather than 16.
+    fn sprite_size(&self, n: u8) -> usize {
+        match n {
+            0 if self.instruction_set >= InstructionSet::SuperChip => 32,
+            n => n as usize
+        }
+    }
 
-        self.canvas.present();
+    /// Whether `instruction` is in the instruction set being run.
+    fn supports(&self, instruction: Instruction) -> bool {
+        instruction.instruction_set() <= self.instruction_set
+    }
 
+    /// Copies the file at `path` into memory at 0x200, where programs start.
+    pub fn load_rom(&mut self, path: impl AsRef<Path>) -> Result<(), Chip8Error> {
+        let rom_data = fs::read(path)?;
+        self.load_bytes(&rom_data)
     }
 
-    pub fn load_rom(&mut self, name: String) {
-        for mem in 0x200..4096 {
-            self.memory[mem] = 0;
+    /// Copies `rom_data` into memory at 0x200, where programs start.
+    pub fn load_bytes(&mut self, rom_data: &[u8]) -> Result<(), Chip8Error> {
+        let start = PROGRAM_START as usize;
+        let max = self.memory.len() - start;
+        if rom_data.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: rom_data.len(), max });
         }
-        let rom_data = fs::File::open(Path::new("./roms/").join(name)).unwrap();
-        let rom_data: Vec<Result<u8, std::io::Error>> = rom_data.bytes().collect();
 
-        for (offset, byte) in rom_data.iter().enumerate() {
-            let byte = byte.as_ref().unwrap();
-            self.memory[0x200 + offset] = *byte;
+        self.memory[start..].fill(0);
+        self.memory[start..start + rom_data.len()].copy_from_slice(rom_data);
+        Ok(())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Changes interpreter behaviour on the fly. Takes effect from the next instruction.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            platform: self.platform,
+            pixels: self.pixels.clone(),
+            memory: self.memory.to_vec(),
+            registers: self.registers,
+            register_i: self.register_i,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            quirks: self.quirks,
+            vblank: self.vblank,
+            rng: self.rng,
+            flags: self.flags,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch
         }
     }
 
-    fn handle_delay_timer(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    /// Puts the machine back into the state of `snapshot`. Fails without changing anything if the snapshot
+    /// could not have come from a `Chip8` set up like this one.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Chip8Error> {
+        let rows_valid = !snapshot.pixels.is_empty() && snapshot.pixels.iter().all(|row| !row.is_empty() && row.len() == snapshot.pixels[0].len());
+        if snapshot.memory.len() != self.memory.len() || snapshot.stack.len() > STACK_SIZE || !rows_valid {
+            return Err(Chip8Error::CorruptSaveState);
+        }
+        if snapshot.platform != self.platform {
+            return Err(Chip8Error::SaveStatePlatform { platform: snapshot.platform });
         }
+        self.pixels = snapshot.pixels.clone();
+        self.memory = snapshot.memory.clone();
+        self.registers = snapshot.registers;
+        self.register_i = snapshot.register_i;
+        self.pc = snapshot.pc;
+        self.stack = snapshot.stack.clone();
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.quirks = snapshot.quirks;
+        self.vblank = snapshot.vblank;
+        self.rng = snapshot.rng;
+        self.flags = snapshot.flags;
+        self.planes = snapshot.planes;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.exited = false;
+        Ok(())
     }
 
-    fn handle_sound_timer(&mut self) {
-        if self.sound_timer > 0 {
-            self.audio_device.resume();
-            self.sound_timer -= 1;
-        } else {
-            self.audio_device.pause();
+    /// Makes CXNN produce the same numbers every run, e.g. for comparing traces or replaying input.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(self.rng.kind(), seed);
+    }
+
+    pub fn rng(&self) -> Rng {
+        self.rng
+    }
+
+    /// Swaps the random number generator, e.g. for the COSMAC VIP routine.
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Traces every instruction executed from now on to `tracer`. `None`, the default, turns tracing off.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    /// Pushes any buffered trace records out. Worth calling before exiting without dropping the `Chip8`.
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.flush(),
+            None => Ok(())
         }
+    }
+
+    /// The framebuffer, indexed `[row][column]`. Each pixel is a bitmask of the planes it is on in, so 0 is
+    /// off and 1 is on for anything but XO-CHIP.
+    pub fn pixels(&self) -> &[Vec<u8>] {
+        &self.pixels
+    }
+
+    /// Whether the buzzer should currently be sounding.
+    pub fn sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// The XO-CHIP audio pattern, if the program has loaded one.
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
 
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn register_i(&self) -> u16 {
+        self.register_i
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.keys
     }
 }
 
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new(Quirks::default())
+    }
+}
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
+/// The registers 5XY2/5XY3 save or load, in the order they go in memory. X can be above Y.
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    if x <= y {
+        (x as usize..=y as usize).collect()
+    } else {
+        (y as usize..=x as usize).rev().collect()
+    }
 }
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        // Generate a square wave
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    fn chip8(platform: Platform, program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::from_profile(&platform.profile());
+        chip8.load_bytes(program).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn add_index_wraps_at_the_top_of_memory() {
+        // LD I, LONG 0xFFFF, LD V0, 2, ADD I, V0
+        let mut chip8 = chip8(Platform::XoChip, &[0xF0, 0x00, 0xFF, 0xFF, 0x60, 0x02, 0xF0, 0x1E]);
+        let mut quirks = chip8.quirks();
+        quirks.index_overflow_sets_vf = true;
+        chip8.set_quirks(quirks);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.register_i(), 0x0001);
+        assert_eq!(chip8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn add_index_overflows_past_the_end_of_its_own_memory() {
+        // LD I, 0xFFF, LD V0, 1, ADD I, V0
+        let program = [0xAF, 0xFF, 0x60, 0x01, 0xF0, 0x1E];
+        for (platform, overflowed) in [(Platform::CosmacVip, 1), (Platform::XoChip, 0)] {
+            let mut chip8 = chip8(platform, &program);
+            let mut quirks = chip8.quirks();
+            quirks.index_overflow_sets_vf = true;
+            chip8.set_quirks(quirks);
+            for _ in 0..3 {
+                chip8.step().unwrap();
+            }
+            assert_eq!(chip8.register_i(), 0x1000, "{}", platform);
+            assert_eq!(chip8.registers()[0xF], overflowed, "{}", platform);
+        }
+    }
+
+    #[test]
+    fn dxy0_draws_nothing_before_super_chip() {
+        // LD I, 0x200, LD VF, 5, DRW V0, V0, 0
+        let program = [0xA2, 0x00, 0x6F, 0x05, 0xD0, 0x00];
+        for platform in [Platform::CosmacVip, Platform::Chip48] {
+            let mut chip8 = chip8(platform, &program);
+            chip8.tick_timers();
+            for _ in 0..3 {
+                chip8.step().unwrap();
+            }
+            assert!(chip8.pixels().iter().flatten().all(|pixel| *pixel == 0), "{}", platform);
+            assert_eq!(chip8.registers()[0xF], 0, "{}", platform);
+        }
+
+        let mut chip8 = chip8(Platform::SuperChip, &program);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        // The program is the sprite, two bytes to a row
+        assert_eq!(chip8.pixels()[0][..16], [1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(chip8.pixels()[1][..16], [0, 1, 1, 0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn newer_instructions_are_unknown_on_older_platforms() {
+        let super_chip: [u16; 9] = [0x00C1, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0xF030, 0xF075, 0xF085];
+        let xo_chip: [u16; 7] = [0x00D1, 0x5012, 0x5013, 0xF000, 0xF101, 0xF002, 0xF03A];
+        let cases = [
+            (Platform::CosmacVip, &super_chip[..]),
+            (Platform::CosmacVip, &xo_chip[..]),
+            (Platform::Chip48, &super_chip[..]),
+            (Platform::Chip48, &xo_chip[..]),
+            (Platform::SuperChip, &xo_chip[..])
+        ];
+        for (platform, opcodes) in cases {
+            for &opcode in opcodes {
+                let mut chip8 = chip8(platform, &opcode.to_be_bytes());
+                match chip8.step() {
+                    Err(Chip8Error::UnknownOpcode { opcode: unknown, address: 0x200 }) => assert_eq!(unknown, opcode),
+                    result => panic!("{:04X} on {}: {:?}", opcode, platform, result)
+                }
+                assert_eq!(chip8.pc(), 0x200);
+            }
+        }
+
+        for platform in [Platform::SuperChip, Platform::XoChip] {
+            for &opcode in &super_chip {
+                assert!(chip8(platform, &opcode.to_be_bytes()).step().is_ok(), "{:04X} on {}", opcode, platform);
+            }
+        }
+    }
+
+    #[test]
+    fn skipping_f000_skips_four_bytes_only_on_xo_chip() {
+        // SE V0, 0, then F000 0x1234
+        let program = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34];
+        for (platform, pc) in [(Platform::SuperChip, 0x204), (Platform::XoChip, 0x206)] {
+            let mut chip8 = chip8(platform, &program);
+            chip8.step().unwrap();
+            assert_eq!(chip8.pc(), pc, "{}", platform);
+        }
+    }
+
+    #[test]
+    fn shift_right_takes_vf_from_the_value_shifted() {
+        // LD V1, 2, LD V2, 3, SHR V1, V2: bit 0 is clear in V1 but set in V2
+        let program = [0x61, 0x02, 0x62, 0x03, 0x81, 0x26];
+        for (shift_uses_vy, v1, vf) in [(true, 1, 1), (false, 1, 0)] {
+            let mut chip8 = chip8(Platform::CosmacVip, &program);
+            let mut quirks = chip8.quirks();
+            quirks.shift_uses_vy = shift_uses_vy;
+            chip8.set_quirks(quirks);
+            for _ in 0..3 {
+                chip8.step().unwrap();
+            }
+            // Taking VF from V1 before the copy would give 0 either way
+            assert_eq!((chip8.registers()[1], chip8.registers()[0xF]), (v1, vf), "shift_uses_vy={}", shift_uses_vy);
         }
     }
 }
@@ -0,0 +1,413 @@
+use std::path::PathBuf;
+
+use chip_8::{chip8::FRAMES_PER_SECOND, config::{DARK_COLOR, LIGHT_COLOR, LOOPS_PER_SECOND, PLANE_2_COLOR, PLANE_3_COLOR, REWIND_MEMORY, REWIND_SECONDS, SCREENSHOT_SCALE}, platform::{Platform, Profile}, rng::RngKind, screenshot::{ImageFormat, Palette}, terminal::Glyphs, trace::{OpcodeClass, TraceFilter, TraceFormat}};
+
+pub const USAGE: &str = "\
+Usage: chip-8 [OPTIONS] <ROM>
+       chip-8 disasm <ROM>
+       chip-8 asm <SOURCE> [-o <OUT>]
+       chip-8 tracediff [OPTIONS] <ROM> <REFERENCE>
+
+Commands:
+  disasm                Print a listing of the ROM as it would be loaded at 0x200
+  asm                   Assemble SOURCE into a ROM. Writes OUT, or SOURCE with a .ch8 extension
+  tracediff             Run the ROM headless and compare every instruction with a REFERENCE trace, in the format
+                        --trace writes. Stops at the first difference. Takes --platform, --quirk, --ips, --rng,
+                        --trace and --trace-format, plus:
+    --seed <N>          Seed for CXNN, 0 by default
+    --input <FILE>      Key presses to replay, one `FRAME +KEY -KEY...` line per frame that changes
+
+Options:
+  --platform <NAME>     Behave like an interpreter: vip, chip48, schip or xochip
+  --quirk <NAME>=<on|off>
+                        Override a single quirk, e.g. --quirk vf_reset=on. May be repeated
+  --ips <N>             Instructions executed per second, at least 60
+  --refresh <N>         Window redraws per second. Timers always count down 60 times a second
+  --scale <N>           Window pixels per chip8 pixel
+  --fg <RRGGBB>         Color of pixels that are on
+  --bg <RRGGBB>         Color of pixels that are off
+  --fg2 <RRGGBB>        Color of pixels only on in XO-CHIP's second bitplane
+  --fg3 <RRGGBB>        Color of pixels on in both XO-CHIP bitplanes
+  --mute                Never play sound
+  --seed <N>            Seed CXNN so every run gets the same random numbers
+  --rng <modern|vip>    Random numbers from a modern generator (the default) or the COSMAC VIP's own routine
+  --tui                 Draw the screen in this terminal instead of a window. Esc or ctrl+C quits
+  --glyphs <half|braille>
+                        Draw the terminal screen with half blocks (the default) or braille dots
+  --headless            Run without a window and print the screen when done. Needs --frames unless debugging or
+                        playing a movie
+  --frames <N>          Number of 60 Hz frames to run for in headless mode. A movie runs to its end by default
+  --load-state <FILE>   Start from a save state instead of the beginning of the ROM
+  --save-state <FILE>   Save the state to FILE when a headless run finishes
+  --screenshot <FILE>   Save the screen to FILE, a .png or .pbm, when a headless run finishes. In a window or
+                        the terminal, F12 saves a PNG next to the ROM and shift+F12 a PBM
+  --screenshot-scale <N>
+                        Image pixels per chip8 pixel in PNG screenshots
+  --record <FILE>       Record the screen to an animated GIF until the run ends. In a window or the terminal, F10
+                        starts and stops a recording next to the ROM
+  --record-movie <FILE> Record the keys pressed in every frame, so the run can be played back exactly
+  --play-movie <FILE>   Play back a recorded movie instead of reading the keyboard
+  --rewind <SECONDS>    Seconds of history to keep for rewinding by holding backspace. 0 turns it off
+  --rewind-memory <MB>  Most memory the rewind history may use
+  --debug               Start paused and read debugger commands from standard input. Type `help` for a list
+  --write-log <FILE>    Log every byte the program writes to memory, with the instruction that wrote it
+  --trace <FILE>        Write the machine state before every instruction to FILE
+  --trace-format <text|json>
+                        Write the trace as text (the default) or as one JSON object per line
+  --trace-range <START>-<END>
+                        Only trace instructions at addresses from START to END, in hex
+  --trace-class <CLASS>[,<CLASS>...]
+                        Only trace flow, alu, index, memory, display, input, timer, sound or unknown instructions
+  -h, --help            Print this message
+";
+
+pub enum Command {
+    Help,
+    Run(Box<Options>),
+    Disassemble(PathBuf),
+    Assemble { source: PathBuf, output: PathBuf },
+    TraceDiff(TraceDiffOptions)
+}
+
+pub struct Options {
+    pub rom: PathBuf,
+    pub profile: Profile,
+    pub loops_per_second: u32,
+    pub scale: Option<u32>,
+    pub foreground: (u8, u8, u8),
+    pub background: (u8, u8, u8),
+    pub plane_2: (u8, u8, u8),
+    pub plane_3: (u8, u8, u8),
+    pub mute: bool,
+    pub seed: Option<u64>,
+    pub rng: RngKind,
+    pub tui: bool,
+    pub glyphs: Glyphs,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub load_state: Option<PathBuf>,
+    pub save_state: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_scale: u32,
+    pub record: Option<PathBuf>,
+    pub record_movie: Option<PathBuf>,
+    pub play_movie: Option<PathBuf>,
+    pub rewind_seconds: u32,
+    pub rewind_memory: usize,
+    pub debug: bool,
+    pub write_log: Option<PathBuf>,
+    pub trace: Option<PathBuf>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter
+}
+
+impl Options {
+    /// The colors for pixels on in no plane, the first, the second and both.
+    pub fn palette(&self) -> Palette {
+        [self.background, self.foreground, self.plane_2, self.plane_3]
+    }
+}
+
+pub struct TraceDiffOptions {
+    pub rom: PathBuf,
+    pub reference: PathBuf,
+    pub profile: Profile,
+    pub seed: u64,
+    pub rng: RngKind,
+    pub input: Option<PathBuf>,
+    pub trace: Option<PathBuf>,
+    pub trace_format: TraceFormat
+}
+
+/// --platform, --quirk and --ips, which every command that runs a ROM takes.
+#[derive(Default)]
+struct ProfileArgs {
+    platform: Option<Platform>,
+    quirk_overrides: Vec<(String, bool)>,
+    instructions_per_second: Option<u32>
+}
+
+impl ProfileArgs {
+    /// Consumes `arg` and its value if it is one of these flags.
+    fn parse(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> Result<bool, String> {
+        match arg {
+            "--platform" => self.platform = Some(value(arg, args.next())?.parse::<Platform>()?),
+            "--quirk" => self.quirk_overrides.push(parse_quirk(&value(arg, args.next())?)?),
+            "--ips" => self.instructions_per_second = Some(instructions_per_second(number(arg, args.next())?)?),
+            _ => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    /// The platform's profile, or the default one, with the overrides applied.
+    fn profile(self) -> Result<Profile, String> {
+        let mut profile = self.platform.map_or_else(Profile::default, |platform| platform.profile());
+        for (name, on) in self.quirk_overrides {
+            profile.quirks.set(&name, on)?;
+        }
+        if let Some(ips) = self.instructions_per_second {
+            profile.instructions_per_second = ips;
+        }
+        Ok(profile)
+    }
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("disasm") => {
+            args.next();
+            parse_disassemble(args)
+        },
+        Some("asm") => {
+            args.next();
+            parse_assemble(args)
+        },
+        Some("tracediff") => {
+            args.next();
+            parse_tracediff(args)
+        },
+        _ => parse_run(args)
+    }
+}
+
+fn parse_assemble(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut source = None;
+    let mut output = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" => output = Some(PathBuf::from(value(&arg, args.next())?)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if source.is_some() => return Err(format!("unexpected argument '{}', only one source file may be given", arg)),
+            _ => source = Some(PathBuf::from(arg))
+        }
+    }
+    let source: PathBuf = source.ok_or("no source file given")?;
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    Ok(Command::Assemble { source, output })
+}
+
+fn parse_disassemble(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut rom = None;
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if rom.is_some() => return Err(format!("unexpected argument '{}', only one ROM may be given", arg)),
+            _ => rom = Some(PathBuf::from(arg))
+        }
+    }
+    Ok(Command::Disassemble(rom.ok_or("no ROM given")?))
+}
+
+fn parse_tracediff(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut paths = Vec::new();
+    let mut profile_args = ProfileArgs::default();
+    let mut seed = 0;
+    let mut rng = RngKind::Modern;
+    let mut input = None;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--seed" => seed = number(&arg, args.next())?,
+            "--rng" => rng = value(&arg, args.next())?.parse()?,
+            "--input" => input = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--trace" => trace = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--trace-format" => trace_format = value(&arg, args.next())?.parse()?,
+            _ if profile_args.parse(&arg, &mut args)? => {},
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => paths.push(PathBuf::from(arg))
+        }
+    }
+
+    let [rom, reference]: [PathBuf; 2] = paths.try_into().map_err(|_| "tracediff needs a ROM and a reference trace")?;
+    Ok(Command::TraceDiff(TraceDiffOptions {
+        rom,
+        reference,
+        profile: profile_args.profile()?,
+        seed,
+        rng,
+        input,
+        trace,
+        trace_format
+    }))
+}
+
+fn parse_run(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut rom = None;
+    let mut profile_args = ProfileArgs::default();
+    let mut options = Options {
+        rom: PathBuf::new(),
+        profile: Profile::default(),
+        loops_per_second: LOOPS_PER_SECOND,
+        scale: None,
+        foreground: DARK_COLOR,
+        background: LIGHT_COLOR,
+        plane_2: PLANE_2_COLOR,
+        plane_3: PLANE_3_COLOR,
+        mute: false,
+        seed: None,
+        rng: RngKind::Modern,
+        tui: false,
+        glyphs: Glyphs::HalfBlock,
+        headless: false,
+        frames: None,
+        load_state: None,
+        save_state: None,
+        screenshot: None,
+        screenshot_scale: SCREENSHOT_SCALE,
+        record: None,
+        record_movie: None,
+        play_movie: None,
+        rewind_seconds: REWIND_SECONDS,
+        rewind_memory: REWIND_MEMORY,
+        debug: false,
+        write_log: None,
+        trace: None,
+        trace_format: TraceFormat::Text,
+        trace_filter: TraceFilter::default()
+    };
+    let mut trace_options = false;
+    let mut glyphs_given = false;
+
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--refresh" => options.loops_per_second = number(&arg, args.next())?,
+            "--scale" => options.scale = Some(number(&arg, args.next())?),
+            "--fg" => options.foreground = color(&arg, args.next())?,
+            "--bg" => options.background = color(&arg, args.next())?,
+            "--fg2" => options.plane_2 = color(&arg, args.next())?,
+            "--fg3" => options.plane_3 = color(&arg, args.next())?,
+            "--mute" => options.mute = true,
+            "--seed" => options.seed = Some(number(&arg, args.next())?),
+            "--rng" => options.rng = value(&arg, args.next())?.parse()?,
+            "--tui" => options.tui = true,
+            "--glyphs" => {
+                options.glyphs = value(&arg, args.next())?.parse()?;
+                glyphs_given = true;
+            },
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(number(&arg, args.next())?),
+            "--load-state" => options.load_state = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--save-state" => options.save_state = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--screenshot" => options.screenshot = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--screenshot-scale" => options.screenshot_scale = number(&arg, args.next())?,
+            "--record" => options.record = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--record-movie" => options.record_movie = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--play-movie" => options.play_movie = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--rewind" => options.rewind_seconds = number(&arg, args.next())?,
+            "--rewind-memory" => options.rewind_memory = number::<usize>(&arg, args.next())?.saturating_mul(1024 * 1024),
+            "--debug" => options.debug = true,
+            "--write-log" => options.write_log = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--trace" => options.trace = Some(PathBuf::from(value(&arg, args.next())?)),
+            "--trace-format" => {
+                options.trace_format = value(&arg, args.next())?.parse()?;
+                trace_options = true;
+            },
+            "--trace-range" => {
+                options.trace_filter.range = Some(address_range(&arg, args.next())?);
+                trace_options = true;
+            },
+            "--trace-class" => {
+                for class in value(&arg, args.next())?.split(',') {
+                    options.trace_filter.classes.push(class.parse::<OpcodeClass>()?);
+                }
+                trace_options = true;
+            },
+            _ if profile_args.parse(&arg, &mut args)? => {},
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if rom.is_some() => return Err(format!("unexpected argument '{}', only one ROM may be given", arg)),
+            _ => rom = Some(PathBuf::from(arg))
+        }
+    }
+
+    options.rom = rom.ok_or("no ROM given")?;
+    options.profile = profile_args.profile()?;
+    if options.loops_per_second == 0 || options.scale == Some(0) || options.screenshot_scale == 0 {
+        return Err(String::from("--refresh, --scale and --screenshot-scale must be greater than 0"));
+    }
+    if trace_options && options.trace.is_none() {
+        return Err(String::from("--trace-format, --trace-range and --trace-class need --trace"));
+    }
+    if glyphs_given && !options.tui {
+        return Err(String::from("--glyphs needs --tui"));
+    }
+    if options.tui && options.headless {
+        return Err(String::from("--tui and --headless can't be used together"));
+    }
+    if options.tui && options.debug {
+        return Err(String::from("--debug reads commands from the terminal, so it can't be used with --tui"));
+    }
+    if options.save_state.is_some() && !options.headless {
+        return Err(String::from("--save-state only works with --headless. In a window, use shift+F1-F9"));
+    }
+    if let Some(path) = &options.screenshot {
+        if !options.headless {
+            return Err(String::from("--screenshot only works with --headless. In a window or the terminal, use F12"));
+        }
+        ImageFormat::from_path(path).map_err(|e| format!("--screenshot: {}", e))?;
+    }
+    if options.record_movie.is_some() && options.headless {
+        return Err(String::from("--record-movie needs a window or --tui to read the keyboard from"));
+    }
+    if (options.record_movie.is_some() || options.play_movie.is_some()) && options.load_state.is_some() {
+        return Err(String::from("movies start from the beginning of the ROM, so they can't be used with --load-state"));
+    }
+    if options.record_movie.is_some() && options.play_movie.is_some() {
+        return Err(String::from("--record-movie and --play-movie can't be used together"));
+    }
+    if options.headless && options.frames.is_none() && !options.debug && options.play_movie.is_none() {
+        return Err(String::from("--headless needs --frames"));
+    }
+
+    Ok(Command::Run(Box::new(options)))
+}
+
+/// At least one instruction a frame, or the timers would run with nothing happening in between.
+fn instructions_per_second(ips: u32) -> Result<u32, String> {
+    if ips < FRAMES_PER_SECOND {
+        return Err(format!("--ips must be at least {}, one instruction per frame, got {}", FRAMES_PER_SECOND, ips));
+    }
+    Ok(ips)
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn number<T: std::str::FromStr>(flag: &str, arg: Option<String>) -> Result<T, String> {
+    let arg = value(flag, arg)?;
+    arg.parse().map_err(|_| format!("{} expects a number, got '{}'", flag, arg))
+}
+
+fn color(flag: &str, arg: Option<String>) -> Result<(u8, u8, u8), String> {
+    let arg = value(flag, arg)?;
+    let hex = arg.trim_start_matches('#');
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("{} expects a color like FF8800, got '{}'", flag, arg))?;
+    Ok(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+fn address_range(flag: &str, arg: Option<String>) -> Result<(u16, u16), String> {
+    let arg = value(flag, arg)?;
+    let hex = |text: &str| u16::from_str_radix(text.trim_start_matches("0x"), 16).ok();
+    arg.split_once('-')
+        .and_then(|(start, end)| Some((hex(start)?, hex(end)?)))
+        .filter(|(start, end)| start <= end)
+        .ok_or_else(|| format!("{} expects a range like 200-2FF, got '{}'", flag, arg))
+}
+
+fn parse_quirk(arg: &str) -> Result<(String, bool), String> {
+    let (name, setting) = arg.split_once('=').ok_or_else(|| format!("--quirk expects NAME=on|off, got '{}'", arg))?;
+    let on = match setting {
+        "on" | "true" | "1" => true,
+        "off" | "false" | "0" => false,
+        _ => return Err(format!("--quirk {} must be on or off, got '{}'", name, setting))
+    };
+    Ok((name.to_string(), on))
+}
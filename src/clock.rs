@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+
+/// Says how many fixed length frames are due, so emulated time keeps pace with the wall clock.
+///
+/// Deadlines are a fixed period apart rather than measured from when the last frame happened to run, so
+/// oversleeping doesn't add up. If the host stalls for longer than `max_catch_up` frames, the frames beyond
+/// that are dropped instead of being run in one burst.
+pub struct FrameClock {
+    period: Duration,
+    next: Instant,
+    max_catch_up: u32
+}
+
+impl FrameClock {
+    /// A clock ticking `frames_per_second` times a second, with the first frame due now.
+    pub fn new(frames_per_second: u32, max_catch_up: u32) -> Self {
+        FrameClock {
+            period: Duration::from_secs(1) / frames_per_second.max(1),
+            next: Instant::now(),
+            max_catch_up: max_catch_up.max(1)
+        }
+    }
+
+    /// Number of frames due by `now`. They count as run, so the next call only returns newer ones.
+    pub fn due(&mut self, now: Instant) -> u32 {
+        let mut frames = 0;
+        while self.next <= now && frames < self.max_catch_up {
+            self.next += self.period;
+            frames += 1;
+        }
+        // Too far behind to catch up, so skip ahead
+        if self.next <= now {
+            self.next = now + self.period;
+        }
+        frames
+    }
+
+    /// When the next frame is due.
+    pub fn next_frame(&self) -> Instant {
+        self.next
+    }
+}
+
+/// Hands out an instruction rate one frame at a time. A rate that isn't a multiple of the frame rate leaves
+/// part of an instruction over each frame, which is carried into the next, so every second runs exactly
+/// `per_second` instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionBudget {
+    per_second: u32,
+    frames_per_second: u32,
+    // Carried over from earlier frames, in units of 1 / frames_per_second instructions
+    remainder: u32
+}
+
+impl InstructionBudget {
+    pub fn new(per_second: u32, frames_per_second: u32) -> Self {
+        InstructionBudget { per_second, frames_per_second: frames_per_second.max(1), remainder: 0 }
+    }
+
+    pub fn per_second(&self) -> u32 {
+        self.per_second
+    }
+
+    /// Number of instructions to run in the next frame.
+    pub fn next_frame(&mut self) -> u32 {
+        let owed = self.per_second as u64 + self.remainder as u64;
+        self.remainder = (owed % self.frames_per_second as u64) as u32;
+        (owed / self.frames_per_second as u64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_due_a_period_apart() {
+        let mut clock = FrameClock::new(60, 4);
+        let start = clock.next_frame();
+        assert_eq!(clock.due(start), 1);
+        assert_eq!(clock.due(start), 0);
+        assert_eq!(clock.due(start + Duration::from_millis(20)), 1);
+        assert_eq!(clock.due(start + Duration::from_millis(50)), 2);
+    }
+
+    #[test]
+    fn a_short_stall_is_caught_up() {
+        let mut clock = FrameClock::new(60, 4);
+        let start = clock.next_frame();
+        assert_eq!(clock.due(start), 1);
+        // 3 periods late: every missed frame runs, and the schedule is unchanged
+        let now = start + Duration::from_secs(1) / 60 * 3;
+        assert_eq!(clock.due(now), 3);
+        assert_eq!(clock.next_frame(), start + Duration::from_secs(1) / 60 * 4);
+    }
+
+    #[test]
+    fn a_long_stall_is_capped_and_skipped() {
+        let mut clock = FrameClock::new(60, 4);
+        let start = clock.next_frame();
+        let now = start + Duration::from_secs(2);
+        assert_eq!(clock.due(now), 4);
+        // The frames beyond the cap are dropped rather than run on the next call
+        assert_eq!(clock.next_frame(), now + Duration::from_secs(1) / 60);
+        assert_eq!(clock.due(now), 0);
+    }
+
+    #[test]
+    fn instruction_budget_carries_the_remainder() {
+        let mut budget = InstructionBudget::new(100, 60);
+        let frames: Vec<u32> = (0..6).map(|_| budget.next_frame()).collect();
+        assert_eq!(frames, [1, 2, 2, 1, 2, 2]);
+        assert_eq!((6..60).map(|_| budget.next_frame()).sum::<u32>() + frames.iter().sum::<u32>(), 100);
+    }
+
+    #[test]
+    fn instruction_budget_of_a_multiple_is_even() {
+        let mut budget = InstructionBudget::new(600, 60);
+        assert!((0..120).all(|_| budget.next_frame() == 10));
+    }
+}
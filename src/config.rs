@@ -0,0 +1,28 @@
+// CONFIG
+// Defaults for the command line options of the same purpose.
+
+/// Color drawn when a pixel is on
+pub const DARK_COLOR: (u8, u8, u8) = (188, 107, 184);
+/// Color drawn when a pixel is off
+pub const LIGHT_COLOR: (u8, u8, u8) = (141, 173, 104);
+/// Color drawn when a pixel is only on in XO-CHIP's second bitplane
+pub const PLANE_2_COLOR: (u8, u8, u8) = (72, 92, 160);
+/// Color drawn when a pixel is on in both XO-CHIP bitplanes
+pub const PLANE_3_COLOR: (u8, u8, u8) = (58, 38, 72);
+
+/// Screen pixels per chip8 pixel in PNG screenshots. 1 keeps the chip8's own resolution.
+pub const SCREENSHOT_SCALE: u32 = 1;
+/// GIF pixels per high resolution chip8 pixel. Low resolution pixels are twice as big.
+pub const GIF_SCALE: u32 = 4;
+
+/// Times per second the window is redrawn and the keyboard read. Emulation always runs at 60 frames a second.
+pub const LOOPS_PER_SECOND: u32 = 60;
+/// Most emulated frames run back to back to make up for the host stalling. Any more are skipped.
+pub const MAX_CATCH_UP_FRAMES: u32 = 6;
+
+/// Seconds of history kept for rewinding.
+pub const REWIND_SECONDS: u32 = 10;
+/// Most memory the rewind history may use, in bytes.
+pub const REWIND_MEMORY: usize = 16 * 1024 * 1024;
+/// Frames between rewind states. Rewinding goes back this many frames per frame.
+pub const REWIND_FRAMES_PER_STATE: u32 = 2;
@@ -0,0 +1,797 @@
+use std::{fmt::{self, Write}, fs::File, io::{self, BufWriter}, path::PathBuf};
+
+use crate::{chip8::{AccessKind, Chip8, MemoryAccess, XO_MEMORY_SIZE}, error::Chip8Error, instruction::{decode, Instruction}};
+
+pub const HELP: &str = "\
+Commands:
+  c, continue              Run until a breakpoint is hit
+  s, step                  Execute one instruction
+  n, next                  Step over subroutine calls
+  finish                   Run until the current subroutine returns
+  until <ADDR>             Run until the program counter reaches ADDR
+  b, break <ADDR> [if <COND>]
+                           Stop at ADDR, optionally only when COND holds
+  b, break if <COND>       Stop whenever COND holds
+  d, delete <N>            Remove breakpoint number N
+  watch <ADDR> [LEN]       Stop after an instruction writes any of the LEN bytes from ADDR
+  rwatch <ADDR> [LEN]      Stop after an instruction reads any of them
+  awatch <ADDR> [LEN]      Stop after an instruction reads or writes any of them
+  unwatch <N>              Remove watchpoint number N
+  log <FILE>               Write every memory write to FILE. `log off` stops
+  info                     List breakpoints and watchpoints
+  regs                     Show registers, timers and stack
+  x <ADDR> [LEN]           Dump LEN bytes of memory from ADDR
+  h, help                  Print this message
+  q, quit                  Quit the emulator
+A condition compares a register, I, DT or ST with a number, e.g. `V3 == 5` or `I >= 0x300`.
+";
+
+/// How many instructions around the program counter the status view shows.
+const CONTEXT_BEFORE: u16 = 3;
+const CONTEXT_AFTER: u16 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8),
+    Index,
+    DelayTimer,
+    SoundTimer
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16
+}
+
+impl Condition {
+    pub fn holds(&self, chip8: &Chip8) -> bool {
+        let actual = match self.operand {
+            Operand::Register(x) => chip8.registers()[x as usize] as u16,
+            Operand::Index => chip8.register_i(),
+            Operand::DelayTimer => chip8.delay_timer() as u16,
+            Operand::SoundTimer => chip8.sound_timer() as u16
+        };
+        match self.comparison {
+            Comparison::Equal => actual == self.value,
+            Comparison::NotEqual => actual != self.value,
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Greater => actual > self.value,
+            Comparison::GreaterOrEqual => actual >= self.value
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operand {
+            Operand::Register(x) => write!(f, "V{:X}", x)?,
+            Operand::Index => write!(f, "I")?,
+            Operand::DelayTimer => write!(f, "DT")?,
+            Operand::SoundTimer => write!(f, "ST")?
+        }
+        let comparison = match self.comparison {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">="
+        };
+        write!(f, " {} 0x{:X}", comparison, self.value)
+    }
+}
+
+/// Stops execution at `address`, whenever `condition` holds, or both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: Option<u16>,
+    pub condition: Option<Condition>
+}
+
+impl Breakpoint {
+    pub fn hit(&self, chip8: &Chip8) -> bool {
+        self.address.is_none_or(|address| chip8.pc() == address)
+            && self.condition.is_none_or(|condition| condition.holds(chip8))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.address, self.condition) {
+            (Some(address), Some(condition)) => write!(f, "0x{:03X} if {}", address, condition),
+            (Some(address), None) => write!(f, "0x{:03X}", address),
+            (None, Some(condition)) => write!(f, "if {}", condition),
+            (None, None) => write!(f, "always")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access
+}
+
+/// Stops execution after an instruction touches memory from `start` to `end`, inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind
+}
+
+impl Watchpoint {
+    /// The part of `access` this watchpoint covers, if any.
+    fn overlap(&self, access: MemoryAccess) -> Option<(u16, u16)> {
+        let watched = match self.kind {
+            WatchKind::Read => access.kind == AccessKind::Read,
+            WatchKind::Write => access.kind == AccessKind::Write,
+            WatchKind::Access => true
+        };
+        let start = (self.start as usize).max(access.start);
+        let end = (self.end as usize).min(access.range().end.checked_sub(1)?);
+        // Both are clamped to the watchpoint, so they fit in a u16
+        (watched && start <= end).then_some((start as u16, end as u16))
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "read",
+            WatchKind::Write => "write",
+            WatchKind::Access => "access"
+        };
+        if self.start == self.end {
+            write!(f, "{} 0x{:03X}", kind, self.start)
+        } else {
+            write!(f, "{} 0x{:03X}..0x{:03X}", kind, self.start, self.end)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    Step,
+    StepOver,
+    StepOut,
+    RunTo(u16),
+    Break(Breakpoint),
+    Delete(usize),
+    Watch(Watchpoint),
+    Unwatch(usize),
+    /// Start logging memory writes to a file, or stop with `None`
+    Log(Option<PathBuf>),
+    Info,
+    Registers,
+    Examine { address: u16, len: u16 },
+    Help,
+    Quit
+}
+
+/// Where a running step over, step out or run to should stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Target {
+    Address(u16),
+    /// The program counter is `address` with at most `depth` return addresses on the stack
+    Return { address: u16, depth: usize },
+    /// The stack is at most `depth` deep
+    Depth(usize)
+}
+
+/// Breakpoints and stepping on top of a `Chip8`. While paused, `run_frame` does nothing, so a frontend can
+/// keep drawing the screen and taking commands.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    write_log: Option<Box<dyn io::Write>>,
+    paused: bool,
+    // Set when execution resumes, so the breakpoint we stopped on doesn't stop us again straight away
+    resumed: bool,
+    target: Option<Target>,
+    quit: bool
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            write_log: None,
+            paused: false,
+            resumed: false,
+            target: None,
+            quit: false
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.target = None;
+    }
+
+    /// Whether the user asked to quit the emulator.
+    pub fn wants_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        (index < self.breakpoints.len()).then(|| self.breakpoints.remove(index))
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    /// Every byte written to memory from now on is logged to `log`, one line each. `None` stops logging.
+    pub fn set_write_log(&mut self, log: Option<Box<dyn io::Write>>) {
+        self.write_log = log;
+    }
+
+    /// Runs a frame like `Chip8::run_frame`, unless paused. Returns a message describing why execution
+    /// stopped if a breakpoint is hit, a step finishes or the program faults.
+    pub fn run_frame(&mut self, chip8: &mut Chip8, instructions: u32) -> Option<String> {
+        if self.paused {
+            return None;
+        }
+        chip8.tick_timers();
+        for _ in 0..instructions {
+            if let Some(message) = self.check_stop(chip8) {
+                return Some(message);
+            }
+            self.resumed = false;
+            match self.execute(chip8) {
+                Ok(None) => {},
+                Ok(Some(message)) => {
+                    self.pause();
+                    return Some(message);
+                },
+                Err(e) => {
+                    self.pause();
+                    return Some(format!("Fault: {}", e));
+                }
+            }
+        }
+        None
+    }
+
+    /// Steps `chip8`, checking the memory the instruction touched against the watchpoints and logging writes.
+    /// Returns a message if a watchpoint was hit.
+    fn execute(&mut self, chip8: &mut Chip8) -> Result<Option<String>, Chip8Error> {
+        let address = chip8.pc();
+        let instruction = decode(chip8.fetch()?);
+        let access = if self.watchpoints.is_empty() && self.write_log.is_none() {
+            None
+        } else {
+            chip8.memory_access(instruction)
+        };
+        let Some(access) = access else {
+            chip8.step()?;
+            return Ok(None);
+        };
+
+        let range = access.range();
+        let before = chip8.memory()[range.clone()].to_vec();
+        chip8.step()?;
+        // A draw waiting for the display leaves the program counter where it was and touches nothing
+        if chip8.pc() == address {
+            return Ok(None);
+        }
+        let after = &chip8.memory()[range];
+
+        if let (AccessKind::Write, Some(log)) = (access.kind, &mut self.write_log) {
+            for (offset, (old, new)) in before.iter().zip(after).enumerate() {
+                let _ = writeln!(log, "{:03X}  {:<16} {:03X}: {:02X} -> {:02X}", address, instruction.to_string(), access.start + offset, old, new);
+            }
+            // Flush now, so the log survives the emulator being killed
+            let _ = log.flush();
+        }
+
+        let Some((index, (start, end))) = self.watchpoints.iter().enumerate()
+            .find_map(|(index, watchpoint)| Some((index, watchpoint.overlap(access)?))) else {
+            return Ok(None);
+        };
+        let bytes: Vec<String> = (start..=end).map(|target| {
+            let offset = target as usize - access.start;
+            match access.kind {
+                AccessKind::Read => format!("0x{:03X}: {:02X}", target, after[offset]),
+                AccessKind::Write => format!("0x{:03X}: {:02X} -> {:02X}", target, before[offset], after[offset])
+            }
+        }).collect();
+        let verb = if access.kind == AccessKind::Read { "read" } else { "wrote" };
+        Ok(Some(format!("Watchpoint {} hit: {} at 0x{:03X} {} {}", index, instruction, address, verb, bytes.join(", "))))
+    }
+
+    fn check_stop(&mut self, chip8: &Chip8) -> Option<String> {
+        let reached = match self.target {
+            Some(Target::Address(address)) => chip8.pc() == address,
+            Some(Target::Return { address, depth }) => chip8.pc() == address && chip8.stack().len() <= depth,
+            Some(Target::Depth(depth)) => chip8.stack().len() <= depth,
+            None => false
+        };
+        if reached {
+            self.pause();
+            return Some(format!("Stopped at 0x{:03X}", chip8.pc()));
+        }
+        if self.resumed {
+            return None;
+        }
+        let hit = self.breakpoints.iter().position(|breakpoint| breakpoint.hit(chip8))?;
+        self.pause();
+        Some(format!("Breakpoint {} hit: {}", hit, self.breakpoints[hit]))
+    }
+
+    /// Carries out `command`, returning what to show the user.
+    pub fn handle(&mut self, chip8: &mut Chip8, command: Command) -> String {
+        match command {
+            Command::Continue => {
+                self.paused = false;
+                self.resumed = true;
+                self.target = None;
+                String::from("Continuing")
+            },
+            Command::Step => self.step(chip8),
+            Command::StepOver => match chip8.fetch().map(decode) {
+                Ok(Instruction::Call { .. }) => {
+                    self.resume_until(Target::Return { address: chip8.pc().wrapping_add(2), depth: chip8.stack().len() });
+                    String::from("Stepping over call")
+                },
+                _ => self.step(chip8)
+            },
+            Command::StepOut => match chip8.stack().len() {
+                0 => String::from("Not in a subroutine"),
+                depth => {
+                    self.resume_until(Target::Depth(depth - 1));
+                    String::from("Running until return")
+                }
+            },
+            Command::RunTo(address) => {
+                self.resume_until(Target::Address(address));
+                format!("Running until 0x{:03X}", address)
+            },
+            Command::Break(breakpoint) => {
+                self.add_breakpoint(breakpoint);
+                format!("Breakpoint {}: {}", self.breakpoints.len() - 1, breakpoint)
+            },
+            Command::Delete(index) => match self.remove_breakpoint(index) {
+                Some(breakpoint) => format!("Deleted breakpoint {}: {}", index, breakpoint),
+                None => format!("No breakpoint {}", index)
+            },
+            Command::Watch(watchpoint) => {
+                self.add_watchpoint(watchpoint);
+                format!("Watchpoint {}: {}", self.watchpoints.len() - 1, watchpoint)
+            },
+            Command::Unwatch(index) => match self.remove_watchpoint(index) {
+                Some(watchpoint) => format!("Deleted watchpoint {}: {}", index, watchpoint),
+                None => format!("No watchpoint {}", index)
+            },
+            Command::Log(None) => {
+                self.set_write_log(None);
+                String::from("Stopped logging memory writes")
+            },
+            Command::Log(Some(path)) => match File::create(&path) {
+                Ok(file) => {
+                    self.set_write_log(Some(Box::new(BufWriter::new(file))));
+                    format!("Logging memory writes to {}", path.display())
+                },
+                Err(e) => format!("Could not open {}: {}", path.display(), e)
+            },
+            Command::Info => {
+                if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+                    return String::from("No breakpoints or watchpoints");
+                }
+                let breakpoints = self.breakpoints.iter().enumerate()
+                    .map(|(index, breakpoint)| format!("Breakpoint {}: {}", index, breakpoint));
+                let watchpoints = self.watchpoints.iter().enumerate()
+                    .map(|(index, watchpoint)| format!("Watchpoint {}: {}", index, watchpoint));
+                let lines: Vec<String> = breakpoints.chain(watchpoints).collect();
+                lines.join("\n")
+            },
+            Command::Registers => status(chip8),
+            Command::Examine { address, len } => hex_dump(chip8.memory(), address, len),
+            Command::Help => HELP.trim_end().to_string(),
+            Command::Quit => {
+                self.quit = true;
+                String::from("Quitting")
+            }
+        }
+    }
+
+    fn step(&mut self, chip8: &mut Chip8) -> String {
+        self.paused = true;
+        match self.execute(chip8) {
+            Ok(None) => status(chip8),
+            Ok(Some(message)) => format!("{}\n{}", message, status(chip8)),
+            Err(e) => format!("Fault: {}\n{}", e, status(chip8))
+        }
+    }
+
+    fn resume_until(&mut self, target: Target) {
+        self.paused = false;
+        self.resumed = true;
+        self.target = Some(target);
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Registers, timers, stack and the instructions around the program counter.
+pub fn status(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    let registers: Vec<String> = chip8.registers().iter().enumerate()
+        .map(|(index, value)| format!("V{:X}={:02X}", index, value))
+        .collect();
+    let _ = writeln!(out, "{}", registers.join(" "));
+    let _ = writeln!(out, "PC={:03X} I={:03X} DT={:02X} ST={:02X}", chip8.pc(), chip8.register_i(), chip8.delay_timer(), chip8.sound_timer());
+    let stack: Vec<String> = chip8.stack().iter().map(|address| format!("{:03X}", address)).collect();
+    let _ = writeln!(out, "Stack: [{}]", stack.join(" "));
+
+    let memory = chip8.memory();
+    let start = chip8.pc().saturating_sub(CONTEXT_BEFORE * 2);
+    for address in (start..chip8.pc().saturating_add(CONTEXT_AFTER * 2)).step_by(2) {
+        if address as usize + 1 >= memory.len() {
+            break;
+        }
+        let opcode = u16::from(memory[address as usize]) << 8 | u16::from(memory[address as usize + 1]);
+        let marker = if address == chip8.pc() { "=>" } else { "  " };
+        let _ = writeln!(out, "{} {:03X}  {:04X}  {}", marker, address, opcode, decode(opcode));
+    }
+    out.trim_end().to_string()
+}
+
+/// Sixteen bytes per line, starting at `address`.
+pub fn hex_dump(memory: &[u8], address: u16, len: u16) -> String {
+    let start = address as usize;
+    let end = (start + len as usize).min(memory.len());
+    if start >= end {
+        return format!("0x{:03X} is outside of memory", address);
+    }
+    let lines: Vec<String> = memory[start..end].chunks(16).enumerate().map(|(row, bytes)| {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        format!("{:03X}: {}", start + row * 16, bytes.join(" "))
+    }).collect();
+    lines.join("\n")
+}
+
+/// Reads a debugger command typed by the user.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&name, args)) = words.split_first() else {
+        return Err(String::from("empty command"));
+    };
+    match (name, args) {
+        ("c" | "continue", []) => Ok(Command::Continue),
+        ("s" | "step", []) => Ok(Command::Step),
+        ("n" | "next", []) => Ok(Command::StepOver),
+        ("finish", []) => Ok(Command::StepOut),
+        ("until", [address]) => Ok(Command::RunTo(parse_address(address)?)),
+        ("b" | "break", ["if", condition @ ..]) => Ok(Command::Break(Breakpoint { address: None, condition: Some(parse_condition(condition)?) })),
+        ("b" | "break", [address]) => Ok(Command::Break(Breakpoint { address: Some(parse_address(address)?), condition: None })),
+        ("b" | "break", [address, "if", condition @ ..]) => Ok(Command::Break(Breakpoint {
+            address: Some(parse_address(address)?),
+            condition: Some(parse_condition(condition)?)
+        })),
+        ("d" | "delete", [index]) => Ok(Command::Delete(index.parse().map_err(|_| format!("expected a breakpoint number, got '{}'", index))?)),
+        ("watch", args) => Ok(Command::Watch(parse_watchpoint(WatchKind::Write, args)?)),
+        ("rwatch", args) => Ok(Command::Watch(parse_watchpoint(WatchKind::Read, args)?)),
+        ("awatch", args) => Ok(Command::Watch(parse_watchpoint(WatchKind::Access, args)?)),
+        ("unwatch", [index]) => Ok(Command::Unwatch(index.parse().map_err(|_| format!("expected a watchpoint number, got '{}'", index))?)),
+        ("log", ["off"]) => Ok(Command::Log(None)),
+        ("log", [path]) => Ok(Command::Log(Some(PathBuf::from(path)))),
+        ("info", []) => Ok(Command::Info),
+        ("regs", []) => Ok(Command::Registers),
+        ("x", [address]) => Ok(Command::Examine { address: parse_address(address)?, len: 64 }),
+        ("x", [address, len]) => Ok(Command::Examine { address: parse_address(address)?, len: parse_number(len)? }),
+        ("h" | "help", []) => Ok(Command::Help),
+        ("q" | "quit", []) => Ok(Command::Quit),
+        _ => Err(format!("unknown command '{}', try 'help'", line.trim()))
+    }
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse()
+    };
+    parsed.map_err(|_| format!("expected a number, got '{}'", text))
+}
+
+// Any 16 bit address is inside XO-CHIP's memory. Smaller memories are checked when the address is used
+fn parse_address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+}
+
+fn parse_watchpoint(kind: WatchKind, args: &[&str]) -> Result<Watchpoint, String> {
+    let (start, len) = match args {
+        [address] => (parse_address(address)?, 1),
+        [address, len] => (parse_address(address)?, parse_number(len)?),
+        _ => return Err(String::from("expected an address and an optional length"))
+    };
+    let end = (start as usize + len as usize).checked_sub(1).filter(|end| *end >= start as usize && *end < XO_MEMORY_SIZE)
+        .ok_or_else(|| format!("{} bytes from 0x{:03X} don't fit in memory", len, start))?;
+    Ok(Watchpoint { start, end: end as u16, kind })
+}
+
+fn parse_condition(words: &[&str]) -> Result<Condition, String> {
+    let [operand, comparison, value] = words else {
+        return Err(String::from("expected a condition like `V3 == 5`"));
+    };
+    let operand = match operand.to_ascii_uppercase().as_str() {
+        "I" => Operand::Index,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        register => register.strip_prefix('V')
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+            .map(Operand::Register)
+            .ok_or_else(|| format!("expected a register, I, DT or ST, got '{}'", operand))?
+    };
+    let comparison = match *comparison {
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        ">" => Comparison::Greater,
+        ">=" => Comparison::GreaterOrEqual,
+        _ => return Err(format!("expected ==, !=, <, <=, > or >=, got '{}'", comparison))
+    };
+    Ok(Condition { operand, comparison, value: parse_number(value)? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    fn xochip(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::from_profile(&Platform::XoChip.profile());
+        chip8.load_bytes(program).unwrap();
+        chip8
+    }
+
+    // CALL 0x206, LD V1, 1, end: JP end, then the subroutine: ADD V2, 1, CALL 0x20C, RET, and its own: LD V3, 3, RET
+    const CALLS: [u8; 16] = [0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x72, 0x01, 0x22, 0x0C, 0x00, 0xEE, 0x63, 0x03, 0x00, 0xEE];
+
+    #[test]
+    fn steps_over_calls() {
+        let mut chip8 = xochip(&CALLS);
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        assert_eq!(debugger.handle(&mut chip8, Command::StepOver), "Stepping over call");
+        assert!(!debugger.is_paused());
+        assert_eq!(debugger.run_frame(&mut chip8, 100).as_deref(), Some("Stopped at 0x202"));
+        assert!(debugger.is_paused());
+        assert_eq!((chip8.registers()[2], chip8.registers()[3], chip8.stack().len()), (1, 3, 0));
+
+        // Anything else is a single step
+        debugger.handle(&mut chip8, Command::StepOver);
+        assert!(debugger.is_paused());
+        assert_eq!((chip8.pc(), chip8.registers()[1]), (0x204, 1));
+    }
+
+    #[test]
+    fn steps_over_a_call_at_the_end_of_memory() {
+        let mut chip8 = xochip(&CALLS);
+        let mut snapshot = chip8.snapshot();
+        snapshot.memory[0xFFFE..].copy_from_slice(&[0x22, 0x06]);
+        snapshot.pc = 0xFFFE;
+        chip8.restore(&snapshot).unwrap();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.handle(&mut chip8, Command::StepOver), "Stepping over call");
+        assert_eq!(debugger.target, Some(Target::Return { address: 0x0000, depth: 0 }));
+    }
+
+    #[test]
+    fn steps_out_of_subroutines() {
+        let mut chip8 = xochip(&CALLS);
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.handle(&mut chip8, Command::StepOut), "Not in a subroutine");
+        // Into the subroutine and the one it calls
+        for _ in 0..3 {
+            debugger.handle(&mut chip8, Command::Step);
+        }
+        assert_eq!((chip8.pc(), chip8.stack().len()), (0x20C, 2));
+
+        assert_eq!(debugger.handle(&mut chip8, Command::StepOut), "Running until return");
+        assert_eq!(debugger.run_frame(&mut chip8, 100).as_deref(), Some("Stopped at 0x20A"));
+        assert_eq!((chip8.stack().len(), chip8.registers()[3]), (1, 3));
+        debugger.handle(&mut chip8, Command::StepOut);
+        assert_eq!(debugger.run_frame(&mut chip8, 100).as_deref(), Some("Stopped at 0x202"));
+        assert_eq!(chip8.stack().len(), 0);
+    }
+
+    #[test]
+    fn runs_to_an_address() {
+        let mut chip8 = xochip(&CALLS);
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.handle(&mut chip8, Command::RunTo(0x20C)), "Running until 0x20C");
+        assert_eq!(debugger.run_frame(&mut chip8, 100).as_deref(), Some("Stopped at 0x20C"));
+        assert_eq!((chip8.pc(), chip8.registers()[2], chip8.registers()[3]), (0x20C, 1, 0));
+        // Once reached, the target is forgotten
+        debugger.handle(&mut chip8, Command::Continue);
+        assert_eq!(debugger.run_frame(&mut chip8, 100), None);
+    }
+
+    #[test]
+    fn breaks_when_a_condition_holds() {
+        // loop: ADD V2, 1, ADD V4, 2, JP loop
+        let mut chip8 = xochip(&[0x72, 0x01, 0x74, 0x02, 0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        let condition = parse_condition(&["V2", "==", "3"]).unwrap();
+        debugger.add_breakpoint(Breakpoint { address: None, condition: Some(condition) });
+        assert_eq!(debugger.run_frame(&mut chip8, 100).as_deref(), Some("Breakpoint 0 hit: if V2 == 0x3"));
+        assert_eq!((chip8.pc(), chip8.registers()[2]), (0x202, 3));
+
+        // Without an address it stops at every instruction while the condition holds, but continuing never
+        // stops at the same one twice
+        let mut stops = Vec::new();
+        loop {
+            debugger.handle(&mut chip8, Command::Continue);
+            if debugger.run_frame(&mut chip8, 100).is_none() {
+                break;
+            }
+            stops.push(chip8.pc());
+        }
+        assert_eq!(stops, [0x204, 0x200]);
+        assert!(chip8.registers()[2] > 3);
+
+        // With an address too, both have to hold
+        let mut chip8 = xochip(&[0x72, 0x01, 0x74, 0x02, 0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        let condition = parse_condition(&["V4", ">=", "6"]).unwrap();
+        debugger.add_breakpoint(Breakpoint { address: Some(0x200), condition: Some(condition) });
+        assert!(debugger.run_frame(&mut chip8, 100).is_some());
+        assert_eq!((chip8.pc(), chip8.registers()[2], chip8.registers()[4]), (0x200, 3, 6));
+    }
+
+    #[test]
+    fn watchpoint_at_the_end_of_xo_chip_memory() {
+        // LD I, LONG 0xFFF0 then LD VF, [I], which reads up to 0x10000, one past the last u16 address
+        let mut chip8 = xochip(&[0xF0, 0x00, 0xFF, 0xF0, 0xFF, 0x65]);
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint { start: 0xFFFE, end: 0xFFFF, kind: WatchKind::Read });
+        let message = debugger.run_frame(&mut chip8, 2).expect("the watchpoint should stop execution");
+        assert!(message.contains("0xFFFE: 00, 0xFFFF: 00"), "{}", message);
+    }
+
+    #[test]
+    fn write_log_at_the_end_of_xo_chip_memory() {
+        // LD I, LONG 0xFFFD then LD [I], V2 writes the last three bytes of memory
+        let mut chip8 = xochip(&[0xF0, 0x00, 0xFF, 0xFD, 0xF2, 0x55]);
+        let mut debugger = Debugger::new();
+        debugger.set_write_log(Some(Box::new(io::sink())));
+        assert_eq!(debugger.run_frame(&mut chip8, 2), None);
+        assert_eq!(chip8.pc(), 0x206);
+    }
+
+    #[test]
+    fn watchpoint_overlap() {
+        let watchpoint = Watchpoint { start: 0x300, end: 0x30F, kind: WatchKind::Write };
+        let write = |start, len| MemoryAccess { kind: AccessKind::Write, start, len };
+        assert_eq!(watchpoint.overlap(write(0x2F8, 0x10)), Some((0x300, 0x307)));
+        assert_eq!(watchpoint.overlap(write(0x310, 4)), None);
+        assert_eq!(watchpoint.overlap(write(0x300, 0)), None);
+        assert_eq!(watchpoint.overlap(MemoryAccess { kind: AccessKind::Read, start: 0x300, len: 1 }), None);
+    }
+
+    #[test]
+    fn parses_commands_and_their_aliases() {
+        let cases = [
+            ("c", Command::Continue),
+            ("continue", Command::Continue),
+            ("  s  ", Command::Step),
+            ("n", Command::StepOver),
+            ("finish", Command::StepOut),
+            ("until 0x20A", Command::RunTo(0x20A)),
+            ("d 2", Command::Delete(2)),
+            ("unwatch 0", Command::Unwatch(0)),
+            ("log off", Command::Log(None)),
+            ("log writes.txt", Command::Log(Some(PathBuf::from("writes.txt")))),
+            ("x 0x300", Command::Examine { address: 0x300, len: 64 }),
+            ("x 768 16", Command::Examine { address: 0x300, len: 16 }),
+            ("info", Command::Info),
+            ("regs", Command::Registers),
+            ("help", Command::Help),
+            ("q", Command::Quit)
+        ];
+        for (line, command) in cases {
+            assert_eq!(parse_command(line), Ok(command), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parses_breakpoints() {
+        let condition = |operand, comparison, value| Some(Condition { operand, comparison, value });
+        assert_eq!(parse_command("b 0x20A"), Ok(Command::Break(Breakpoint { address: Some(0x20A), condition: None })));
+        assert_eq!(parse_command("break if V3 == 5"), Ok(Command::Break(Breakpoint {
+            address: None,
+            condition: condition(Operand::Register(3), Comparison::Equal, 5)
+        })));
+        assert_eq!(parse_command("b 0x300 if i >= 0xFFF"), Ok(Command::Break(Breakpoint {
+            address: Some(0x300),
+            condition: condition(Operand::Index, Comparison::GreaterOrEqual, 0xFFF)
+        })));
+        assert_eq!(parse_command("b if DT != 0"), Ok(Command::Break(Breakpoint {
+            address: None,
+            condition: condition(Operand::DelayTimer, Comparison::NotEqual, 0)
+        })));
+    }
+
+    #[test]
+    fn parses_watchpoints() {
+        assert_eq!(parse_command("watch 0x300"), Ok(Command::Watch(Watchpoint { start: 0x300, end: 0x300, kind: WatchKind::Write })));
+        assert_eq!(parse_command("rwatch 0x300 4"), Ok(Command::Watch(Watchpoint { start: 0x300, end: 0x303, kind: WatchKind::Read })));
+        assert_eq!(parse_command("awatch 0xFFFF 1"), Ok(Command::Watch(Watchpoint { start: 0xFFFF, end: 0xFFFF, kind: WatchKind::Access })));
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        let errors = [
+            ("", "empty command"),
+            ("   ", "empty command"),
+            ("jump", "unknown command"),
+            ("c now", "unknown command"),
+            ("b", "unknown command"),
+            ("b 0x10000", "expected a number"),
+            ("b zz", "expected a number"),
+            ("b if V3", "expected a condition"),
+            ("b if V3 == 5 6", "expected a condition"),
+            ("b if VG == 5", "expected a register"),
+            ("b if V10 == 5", "expected a register"),
+            ("b if V3 =< 5", "expected =="),
+            ("b if V3 == -1", "expected a number"),
+            ("d one", "expected a breakpoint number"),
+            ("unwatch -1", "expected a watchpoint number"),
+            ("watch", "expected an address"),
+            ("watch 1 2 3", "expected an address"),
+            ("watch 0x300 0", "don't fit"),
+            ("watch 0xFFFF 2", "don't fit"),
+            ("x 0x300 lots", "expected a number")
+        ];
+        for (line, error) in errors {
+            match parse_command(line) {
+                Err(e) => assert!(e.contains(error), "'{}' gave '{}', expected '{}'", line, e, error),
+                Ok(command) => panic!("'{}' parsed as {:?}", line, command)
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::{collections::BTreeSet, fmt};
+
+use crate::{chip8::{PROGRAM_START, XO_MEMORY_SIZE}, error::Chip8Error, instruction::{decode, Instruction}};
+
+/// One line of a listing: an instruction, or a stray byte at the end of the ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Set when something jumps to or calls this address.
+    pub label: Option<String>,
+    pub text: String
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, "{}:", label)?;
+        }
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(f, "{:03X}  {:<12} {}", self.address, bytes.join(" "), self.text)
+    }
+}
+
+/// The label used for a jump or call target.
+pub fn label(address: u16) -> String {
+    format!("L{:03X}", address)
+}
+
+/// Disassembles `rom` as if it had been loaded at 0x200. The ROM is read straight through, so data
+/// shows up as whatever instructions its bytes happen to spell. Fails if the ROM wouldn't fit in even
+/// XO-CHIP's 64 KiB of memory.
+pub fn disassemble(rom: &[u8]) -> Result<Vec<Line>, Chip8Error> {
+    let max = XO_MEMORY_SIZE - PROGRAM_START as usize;
+    if rom.len() > max {
+        return Err(Chip8Error::RomTooLarge { size: rom.len(), max });
+    }
+
+    // Walk the ROM once to find where every instruction starts
+    let mut decoded = Vec::new();
+    let mut offset = 0;
+    while offset + 1 < rom.len() {
+        let instruction = decode(u16::from(rom[offset]) << 8 | u16::from(rom[offset + 1]));
+        let size = if offset + instruction.size() as usize <= rom.len() { instruction.size() as usize } else { 2 };
+        decoded.push((offset, instruction, size));
+        offset += size;
+    }
+
+    // Only label targets that land on the start of a line
+    let starts: BTreeSet<u16> = decoded.iter().map(|(offset, _, _)| PROGRAM_START + *offset as u16).collect();
+    let targets: BTreeSet<u16> = decoded.iter()
+        .filter_map(|(_, instruction, _)| instruction.target())
+        .filter(|target| starts.contains(target))
+        .collect();
+    let name = |address: u16| if targets.contains(&address) { label(address) } else { format!("0x{:03X}", address) };
+
+    let mut lines: Vec<Line> = decoded.into_iter().map(|(offset, instruction, size)| {
+        let address = PROGRAM_START + offset as u16;
+        let mut text = String::new();
+        let _ = instruction.write_mnemonic(&mut text, &name);
+        if instruction == Instruction::LoadLongIndex && size == 4 {
+            text.push_str(&format!(" 0x{:02X}{:02X}", rom[offset + 2], rom[offset + 3]));
+        }
+        Line {
+            address,
+            bytes: rom[offset..offset + size].to_vec(),
+            label: targets.contains(&address).then(|| label(address)),
+            text
+        }
+    }).collect();
+
+    if offset < rom.len() {
+        lines.push(Line {
+            address: PROGRAM_START + offset as u16,
+            bytes: vec![rom[offset]],
+            label: None,
+            text: format!("DB 0x{:02X}", rom[offset])
+        });
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(rom: &[u8]) -> Vec<String> {
+        disassemble(rom).unwrap().into_iter().map(|line| line.text).collect()
+    }
+
+    #[test]
+    fn lists_every_instruction_set() {
+        let chip_8 = [0x00, 0xE0, 0x6A, 0x12, 0x8A, 0xB6, 0xBA, 0xBC, 0xD1, 0x25, 0xF2, 0x55, 0xC3, 0x0F, 0xE4, 0x9E, 0x00, 0xEE];
+        assert_eq!(texts(&chip_8), [
+            "CLS", "LD VA, 0x12", "SHR VA, VB", "JP V0, 0xABC", "DRW V1, V2, 5", "LD [I], V2", "RND V3, 0x0F", "SKP V4", "RET"
+        ]);
+
+        let super_chip = [0x00, 0xC4, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFD, 0x00, 0xFE, 0x00, 0xFF, 0xD0, 0x10, 0xF5, 0x30, 0xF6, 0x75, 0xF7, 0x85];
+        assert_eq!(texts(&super_chip), [
+            "SCD 4", "SCR", "SCL", "EXIT", "LOW", "HIGH", "DRW V0, V1, 0", "LD HF, V5", "LD R, V6", "LD V7, R"
+        ]);
+
+        let xo_chip = [0x00, 0xD3, 0x51, 0x22, 0x51, 0x23, 0xF2, 0x01, 0xF0, 0x02, 0xF8, 0x3A];
+        assert_eq!(texts(&xo_chip), ["SCU 3", "SAVE V1, V2", "LOAD V1, V2", "PLANE 2", "AUDIO", "PITCH V8"]);
+    }
+
+    #[test]
+    fn long_loads_take_four_bytes() {
+        let lines = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0]).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0].address, lines[0].bytes.as_slice()), (0x200, &[0xF0, 0x00, 0x12, 0x34][..]));
+        assert_eq!(lines[0].text, "LD I, LONG 0x1234");
+        assert_eq!((lines[1].address, lines[1].text.as_str()), (0x204, "CLS"));
+        assert_eq!(lines[0].to_string(), "200  F0 00 12 34  LD I, LONG 0x1234");
+
+        // Cut off by the end of the ROM, only the two bytes there are shown
+        let lines = disassemble(&[0x00, 0xE0, 0xF0, 0x00]).unwrap();
+        assert_eq!((lines[1].address, lines[1].bytes.as_slice()), (0x202, &[0xF0, 0x00][..]));
+        assert_eq!(lines[1].text, "LD I, LONG");
+        let lines = disassemble(&[0xF0, 0x00, 0x12]).unwrap();
+        assert_eq!(lines.iter().map(|line| line.bytes.len()).collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(lines[1].text, "DB 0x12");
+    }
+
+    #[test]
+    fn an_odd_byte_at_the_end_is_data() {
+        let lines = disassemble(&[0x00, 0xE0, 0xAB]).unwrap();
+        assert_eq!(lines[1], Line { address: 0x202, bytes: vec![0xAB], label: None, text: String::from("DB 0xAB") });
+        assert_eq!(texts(&[0xAB]), ["DB 0xAB"]);
+        assert!(disassemble(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn labels_only_targets_that_start_a_line() {
+        // JP 0x206, JP V0, 0x202, CALL 0x203, RET
+        let lines = disassemble(&[0x12, 0x06, 0xB2, 0x02, 0x22, 0x03, 0x00, 0xEE]).unwrap();
+        let labels: Vec<Option<&str>> = lines.iter().map(|line| line.label.as_deref()).collect();
+        assert_eq!(labels, [None, None, None, Some("L206")]);
+        assert_eq!(texts(&[0x12, 0x06, 0xB2, 0x02, 0x22, 0x03, 0x00, 0xEE]), ["JP L206", "JP V0, 0x202", "CALL 0x203", "RET"]);
+        assert_eq!(lines[3].to_string(), "L206:\n206  00 EE        RET");
+    }
+
+    #[test]
+    fn rejects_roms_too_big_for_memory() {
+        let max = XO_MEMORY_SIZE - PROGRAM_START as usize;
+        let lines = disassemble(&vec![0; max]).unwrap();
+        assert_eq!(lines.last().unwrap().address, 0xFFFE);
+        assert!(matches!(disassemble(&vec![0; max + 1]), Err(Chip8Error::RomTooLarge { size, .. }) if size == max + 1));
+    }
+}
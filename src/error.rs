@@ -0,0 +1,60 @@
+use std::{fmt, io};
+
+use crate::platform::Platform;
+
+/// Everything that can stop a program from running.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The instruction at `address` is not one the interpreter knows.
+    UnknownOpcode { opcode: u16, address: u16 },
+    /// 00EE at `address` tried to return with nothing on the stack.
+    StackUnderflow { address: u16 },
+    /// 2NNN at `address` tried to call a subroutine with the stack already full.
+    StackOverflow { address: u16 },
+    /// The program counter left memory, so there is no instruction to fetch.
+    PcOutOfRange { pc: u16 },
+    /// The instruction at `address` tried to access `target`, which is past the end of memory.
+    MemoryOutOfRange { address: u16, target: usize },
+    /// The ROM does not fit in the memory available to programs.
+    RomTooLarge { size: usize, max: usize },
+    /// The save state was written by a version of the format this build can't read.
+    SaveStateVersion { version: u16 },
+    /// The save state was made with a different `--platform`, or none if `platform` is `None`.
+    SaveStatePlatform { platform: Option<Platform> },
+    /// The save state is truncated, or isn't a save state at all.
+    CorruptSaveState,
+    Io(io::Error)
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, address } => write!(f, "unknown opcode {:04X} at {:03X}", opcode, address),
+            Chip8Error::StackUnderflow { address } => write!(f, "return with an empty stack at {:03X}", address),
+            Chip8Error::StackOverflow { address } => write!(f, "stack overflow calling a subroutine at {:03X}", address),
+            Chip8Error::PcOutOfRange { pc } => write!(f, "program counter {:03X} is outside of memory", pc),
+            Chip8Error::MemoryOutOfRange { address, target } => write!(f, "instruction at {:03X} accessed {:03X}, which is outside of memory", address, target),
+            Chip8Error::RomTooLarge { size, max } => write!(f, "ROM is {} bytes, but at most {} bytes fit in memory", size, max),
+            Chip8Error::SaveStateVersion { version } => write!(f, "save state is version {}, which this build can't read", version),
+            Chip8Error::SaveStatePlatform { platform: Some(platform) } => write!(f, "save state is for --platform {}", platform),
+            Chip8Error::SaveStatePlatform { platform: None } => write!(f, "save state is for running without --platform"),
+            Chip8Error::CorruptSaveState => write!(f, "save state is corrupt"),
+            Chip8Error::Io(e) => e.fmt(f)
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
@@ -1,114 +1,83 @@
-// Writing the font to 0x050 to 0x09F
-pub fn write_font(ptr: &mut [u8; 4096]) {
-    // Font for 0
-    ptr[0x050] = 0xF0;
-    ptr[0x051] = 0x90;
-    ptr[0x052] = 0x90;
-    ptr[0x053] = 0x90;
-    ptr[0x054] = 0xF0;
+/// The first address of the font. Each character is 5 bytes long, so the font runs to 0x09F.
+pub const FONT_ADDRESS: u16 = 0x050;
+/// The first address of the large font used by FX30. Each character is 10 bytes long, so it runs to 0x13F.
+pub const BIG_FONT_ADDRESS: u16 = 0x0A0;
 
-    // Font for 1
-    ptr[0x055] = 0x20;
-    ptr[0x056] = 0x60;
-    ptr[0x057] = 0x20;
-    ptr[0x058] = 0x20;
-    ptr[0x059] = 0x70;
-
-    // Font for 2
-    ptr[0x05A] = 0xF0;
-    ptr[0x05B] = 0x10;
-    ptr[0x05C] = 0xF0;
-    ptr[0x05D] = 0x80;
-    ptr[0x05E] = 0xF0;
-
-    // 3
-    ptr[0x05F] = 0xf0;
-    ptr[0x060] = 0x10;
-    ptr[0x061] = 0xf0;
-    ptr[0x062] = 0x10;
-    ptr[0x063] = 0xf0;
-
-    // 4
-    ptr[0x064] = 0x90;
-    ptr[0x065] = 0x90;
-    ptr[0x066] = 0xf0;
-    ptr[0x067] = 0x10;
-    ptr[0x068] = 0x10;
-
-    // 5
-    ptr[0x069] = 0xf0;
-    ptr[0x06a] = 0x80;
-    ptr[0x06b] = 0xf0;
-    ptr[0x06c] = 0x10;
-    ptr[0x06d] = 0xf0;
-
-    // 6
-    ptr[0x06e] = 0xf0;
-    ptr[0x06f] = 0x80;
-    ptr[0x070] = 0xf0;
-    ptr[0x071] = 0x90;
-    ptr[0x072] = 0xf0;
-
-    // 7
-    ptr[0x073] = 0xf0;
-    ptr[0x074] = 0x10;
-    ptr[0x075] = 0x20;
-    ptr[0x076] = 0x40;
-    ptr[0x077] = 0x40;
-
-    // 8
-    ptr[0x078] = 0xf0;
-    ptr[0x079] = 0x90;
-    ptr[0x07a] = 0xf0;
-    ptr[0x07b] = 0x90;
-    ptr[0x07c] = 0xf0;
-
-    // 9
-    ptr[0x07d] = 0xf0;
-    ptr[0x07e] = 0x90;
-    ptr[0x07f] = 0xf0;
-    ptr[0x080] = 0x10;
-    ptr[0x081] = 0xf0;
-
-    // a
-    ptr[0x082] = 0xf0;
-    ptr[0x083] = 0x90;
-    ptr[0x084] = 0xf0;
-    ptr[0x085] = 0x90;
-    ptr[0x086] = 0x90;
-
-    // b
-    ptr[0x087] = 0xe0;
-    ptr[0x088] = 0x90;
-    ptr[0x089] = 0xe0;
-    ptr[0x08a] = 0x90;
-    ptr[0x08b] = 0xe0;
+/// The hexadecimal digit sprites shipped with an interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Font {
+    /// The digits from the COSMAC VIP's ROM.
+    CosmacVip,
+    /// The digits used by CHIP-48 and later interpreters.
+    Chip48
+}
 
-    // c
-    ptr[0x08c] = 0xf0;
-    ptr[0x08d] = 0x80;
-    ptr[0x08e] = 0x80;
-    ptr[0x08f] = 0x80;
-    ptr[0x090] = 0xf0;
+const COSMAC_VIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // a
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // b
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // c
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // d
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // e
+    0xF0, 0x80, 0xF0, 0x80, 0x80  // f
+];
 
-    // d
-    ptr[0x091] = 0xe0;
-    ptr[0x092] = 0x90;
-    ptr[0x093] = 0x90;
-    ptr[0x094] = 0x90;
-    ptr[0x095] = 0xe0;
+const CHIP48_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // a
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // b
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // c
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // d
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // e
+    0xF0, 0x80, 0xF0, 0x80, 0x80  // f
+];
 
-    // e
-    ptr[0x096] = 0xf0;
-    ptr[0x097] = 0x80;
-    ptr[0x098] = 0xf0;
-    ptr[0x099] = 0x80;
-    ptr[0x09a] = 0xf0;
+// 8x10 digits for SUPER-CHIP's FX30. The original only had 0-9; A-F follow XO-CHIP.
+const BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // a
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // b
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // c
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // d
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // e
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // f
+];
 
-    // f
-    ptr[0x09b] = 0xf0;
-    ptr[0x09c] = 0x80;
-    ptr[0x09d] = 0xf0;
-    ptr[0x09e] = 0x80;
-    ptr[0x09f] = 0x80;
+// Writing the font to 0x050 to 0x09F, and the large font after it
+pub fn write_font(memory: &mut [u8], font: Font) {
+    let glyphs = match font {
+        Font::CosmacVip => &COSMAC_VIP_FONT,
+        Font::Chip48 => &CHIP48_FONT
+    };
+    let start = FONT_ADDRESS as usize;
+    memory[start..start + glyphs.len()].copy_from_slice(glyphs);
+    let start = BIG_FONT_ADDRESS as usize;
+    memory[start..start + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
 }
@@ -0,0 +1,500 @@
+use std::{io::{self, Write}, path::PathBuf, sync::mpsc::Receiver, thread, time::Instant};
+
+use crate::{audio::Sound, chip8::{Chip8, FRAMES_PER_SECOND}, clock::{FrameClock, InstructionBudget}, config::{DARK_COLOR, GIF_SCALE, LIGHT_COLOR, LOOPS_PER_SECOND, MAX_CATCH_UP_FRAMES, REWIND_FRAMES_PER_STATE, PLANE_2_COLOR, PLANE_3_COLOR, REWIND_MEMORY, REWIND_SECONDS, SCREENSHOT_SCALE}, debugger::{self, Debugger}, error::Chip8Error, movie::Movie, platform::Profile, recording::GifRecorder, rewind::Rewind, savestate, screenshot::{self, ImageFormat, Palette}};
+
+/// Where the screen is shown, the sound played and the keypad read. `Runner` drives one of these, so the
+/// interpreter never needs to know whether it is in a window, a terminal or a test.
+pub trait Frontend {
+    /// Shows a frame. Each pixel is a bitmask of the planes it is on in, and rows may be 64 or 128 wide.
+    fn present(&mut self, pixels: &[Vec<u8>]);
+
+    /// Plays `sound` until told otherwise, or nothing when it is `None`. Called every loop, so
+    /// implementations should be cheap when the sound hasn't changed.
+    fn play_sound(&mut self, sound: Option<Sound>);
+
+    /// Reads what the user has done since the last call.
+    fn poll_input(&mut self) -> Input;
+
+    /// Shows a short status message, such as a fault or that a slot was saved.
+    fn show_message(&mut self, message: &str);
+}
+
+/// The keypad and the emulator's own controls, as read by `Frontend::poll_input`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Input {
+    /// Keypad keys held down, indexed by key
+    pub keys: [bool; 16],
+    /// The rewind key is held down
+    pub rewinding: bool,
+    /// The user closed the window or otherwise asked to stop
+    pub quit: bool,
+    /// Save state slots pressed since the last call, in order
+    pub slot_requests: Vec<SlotRequest>,
+    /// Screenshots asked for since the last call
+    pub screenshots: Vec<ImageFormat>,
+    /// The GIF key was pressed, to start recording or to stop and save the recording in progress
+    pub toggle_gif: bool
+}
+
+/// Saving to or loading from one of the numbered save state slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotRequest {
+    Save(u8),
+    Load(u8)
+}
+
+/// How the runner behaves, whatever the frontend. The defaults come from config.rs.
+#[derive(Clone, Debug)]
+pub struct RunnerOptions {
+    /// Redraws per second. The emulation itself always runs at `FRAMES_PER_SECOND`
+    pub loops_per_second: u32,
+    pub mute: bool,
+    /// Save state slots, screenshots and GIFs are kept next to this file. Without it, slot, screenshot and
+    /// GIF requests do nothing.
+    pub rom: Option<PathBuf>,
+    /// Seconds of history to keep for rewinding. 0 turns rewinding off.
+    pub rewind_seconds: u32,
+    /// Most memory the rewind history may use, in bytes
+    pub rewind_memory: usize,
+    /// Colors of PNG screenshots and GIFs
+    pub palette: Palette,
+    /// Screen pixels per chip8 pixel in PNG screenshots
+    pub screenshot_scale: u32,
+    /// GIF pixels per high resolution chip8 pixel
+    pub gif_scale: u32
+}
+
+impl Default for RunnerOptions {
+    fn default() -> Self {
+        RunnerOptions {
+            loops_per_second: LOOPS_PER_SECOND,
+            mute: false,
+            rom: None,
+            rewind_seconds: REWIND_SECONDS,
+            rewind_memory: REWIND_MEMORY,
+            palette: [LIGHT_COLOR, DARK_COLOR, PLANE_2_COLOR, PLANE_3_COLOR],
+            screenshot_scale: SCREENSHOT_SCALE,
+            gif_scale: GIF_SCALE
+        }
+    }
+}
+
+/// Runs a `Chip8` core in real time on a `Frontend`, with save state slots, rewinding and movies.
+pub struct Runner<F: Frontend> {
+    frontend: F,
+    instructions: InstructionBudget,
+    options: RunnerOptions,
+    // Emulated frames and redraws are due at different rates
+    frame_clock: FrameClock,
+    redraw_clock: FrameClock,
+    quit: bool,
+    // Once the program faults it stops running, but the frontend keeps showing the last frame
+    fault: Option<Chip8Error>,
+    rewind: Option<Rewind>,
+    // The rewind key is held down
+    rewinding: bool,
+    movie: Option<MovieMode>,
+    gif: Option<GifRecorder>
+}
+
+enum MovieMode {
+    Recording(Movie),
+    Playing { movie: Movie, frame: usize }
+}
+
+impl<F: Frontend> Runner<F> {
+    pub fn new(frontend: F, profile: &Profile, options: RunnerOptions) -> Self {
+        let rewind = (options.rewind_seconds > 0).then(|| {
+            Rewind::new(options.rewind_seconds, FRAMES_PER_SECOND, REWIND_FRAMES_PER_STATE, options.rewind_memory)
+        });
+
+        Runner {
+            frontend,
+            instructions: profile.instruction_budget(),
+            frame_clock: FrameClock::new(FRAMES_PER_SECOND, MAX_CATCH_UP_FRAMES),
+            redraw_clock: FrameClock::new(options.loops_per_second, 1),
+            options,
+            quit: false,
+            fault: None,
+            rewind,
+            rewinding: false,
+            movie: None,
+            gif: None
+        }
+    }
+
+    pub fn frontend(&self) -> &F {
+        &self.frontend
+    }
+
+    pub fn frontend_mut(&mut self) -> &mut F {
+        &mut self.frontend
+    }
+
+    /// Records the keys of every frame that runs into `movie`. Slots and rewinding are turned off, since
+    /// they would make the recording impossible to play back.
+    pub fn record_movie(&mut self, movie: Movie) {
+        self.movie = Some(MovieMode::Recording(movie));
+        self.rewind = None;
+    }
+
+    /// Takes the keys from `movie` instead of the frontend, at the speed it was recorded at.
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.instructions = movie.instruction_budget();
+        self.movie = Some(MovieMode::Playing { movie, frame: 0 });
+        self.rewind = None;
+    }
+
+    /// The movie being recorded or played, if any.
+    pub fn take_movie(&mut self) -> Option<Movie> {
+        match self.movie.take()? {
+            MovieMode::Recording(movie) | MovieMode::Playing { movie, .. } => Some(movie)
+        }
+    }
+
+    /// Adds the screen of every emulated frame to `recorder`, until the GIF key stops it.
+    pub fn record_gif(&mut self, recorder: GifRecorder) {
+        self.gif = Some(recorder);
+    }
+
+    /// The GIF being recorded, if any. It still needs finishing.
+    pub fn take_gif(&mut self) -> Option<GifRecorder> {
+        self.gif.take()
+    }
+
+    /// Runs `chip8` until the user quits. If the program faulted along the way, the fault is returned.
+    pub fn run(&mut self, chip8: &mut Chip8) -> Result<(), Chip8Error> {
+        while !self.quit {
+            self.single_loop(chip8, None, false);
+        }
+        match self.fault.take() {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// Runs `chip8` under `debugger` until the user quits, from the frontend or the debugger. Each line
+    /// received from `commands` is a debugger command; replies and the reasons execution stopped are printed
+    /// to stdout. Without `commands` there is nobody to type, so no prompt is shown.
+    pub fn run_debugged(&mut self, chip8: &mut Chip8, debugger: &mut Debugger, commands: Option<&Receiver<String>>) {
+        if debugger.is_paused() {
+            println!("{}", debugger::status(chip8));
+        }
+        let interactive = commands.is_some();
+        if interactive && debugger.is_paused() {
+            prompt();
+        }
+        while !self.quit && !debugger.wants_quit() {
+            for line in commands.into_iter().flat_map(Receiver::try_iter) {
+                if !line.trim().is_empty() {
+                    match debugger::parse_command(&line) {
+                        Ok(command) => println!("{}", debugger.handle(chip8, command)),
+                        Err(e) => println!("error: {}", e)
+                    }
+                }
+                if debugger.is_paused() {
+                    prompt();
+                }
+            }
+            self.single_loop(chip8, Some(debugger), interactive);
+        }
+    }
+
+    /// Handles input, runs however many frames are due and redraws if it is time to, then sleeps until the
+    /// next of those is due.
+    fn single_loop(&mut self, chip8: &mut Chip8, mut debugger: Option<&mut Debugger>, interactive: bool) {
+        let sound = (chip8.sound_playing() && !self.options.mute).then(|| Sound::of(chip8));
+        self.frontend.play_sound(sound);
+
+        let input = self.frontend.poll_input();
+        self.quit |= input.quit;
+        self.rewinding = input.rewinding;
+        if self.movie.is_none() {
+            self.handle_slots(chip8, &input.slot_requests);
+        }
+        self.handle_screenshots(chip8, &input.screenshots);
+        if input.toggle_gif {
+            self.toggle_gif();
+        }
+
+        for _ in 0..self.frame_clock.due(Instant::now()) {
+            self.emulate_frame(chip8, debugger.as_deref_mut(), interactive, input.keys);
+            self.record_gif_frame(chip8);
+        }
+        if self.redraw_clock.due(Instant::now()) > 0 {
+            self.frontend.present(chip8.pixels());
+        }
+
+        let wake = self.frame_clock.next_frame().min(self.redraw_clock.next_frame());
+        thread::sleep(wake.saturating_duration_since(Instant::now()));
+    }
+
+    /// Runs one emulated frame with `keys` held, or goes back one step in the rewind history.
+    fn emulate_frame(&mut self, chip8: &mut Chip8, debugger: Option<&mut Debugger>, interactive: bool, keys: [bool; 16]) {
+        let keys = match &self.movie {
+            Some(MovieMode::Playing { movie, frame }) => movie.keys(*frame).unwrap_or([false; 16]),
+            _ => keys
+        };
+        chip8.set_keys(keys);
+
+        if let (true, Some(rewind)) = (self.rewinding, &mut self.rewind) {
+            // Going back to before a fault lets the program run again
+            if rewind.rewind(chip8) {
+                self.fault = None;
+            }
+            return;
+        }
+
+        let instructions = self.instructions.next_frame();
+        let ran = match debugger {
+            Some(debugger) => {
+                let running = !debugger.is_paused();
+                if let Some(message) = debugger.run_frame(chip8, instructions) {
+                    println!("{}\n{}", message, debugger::status(chip8));
+                    if interactive {
+                        prompt();
+                    }
+                }
+                running
+            },
+            None => self.fault.is_none() && match chip8.run_frame(instructions) {
+                Ok(()) => true,
+                Err(e) => {
+                    self.frontend.show_message(&e.to_string());
+                    self.fault = Some(e);
+                    false
+                }
+            }
+        };
+        // The program is done, like a SUPER-CHIP game returning to the calculator
+        if chip8.has_exited() {
+            self.quit = true;
+        }
+        if ran {
+            if let Some(rewind) = &mut self.rewind {
+                rewind.record(chip8);
+            }
+            self.advance_movie(keys);
+        }
+    }
+
+    /// Adds `keys` to the movie being recorded, or moves on to the next frame of the one being played.
+    fn advance_movie(&mut self, keys: [bool; 16]) {
+        match &mut self.movie {
+            Some(MovieMode::Recording(movie)) => movie.record(keys),
+            Some(MovieMode::Playing { movie, frame }) => {
+                *frame += 1;
+                if *frame == movie.len() {
+                    self.frontend.show_message("movie finished");
+                }
+            },
+            None => {}
+        }
+    }
+
+    /// Saves or loads the slots in `requests`, reporting how it went through the frontend.
+    fn handle_slots(&mut self, chip8: &mut Chip8, requests: &[SlotRequest]) {
+        let Some(rom) = &self.options.rom else {
+            return;
+        };
+        for request in requests {
+            let message = match *request {
+                SlotRequest::Save(slot) => match savestate::save(chip8, savestate::slot_path(rom, slot)) {
+                    Ok(()) => format!("saved slot {}", slot),
+                    Err(e) => format!("could not save slot {}: {}", slot, e)
+                },
+                SlotRequest::Load(slot) => match savestate::load(chip8, savestate::slot_path(rom, slot)) {
+                    Ok(()) => {
+                        // The loaded state is a fresh start, even if the program had faulted
+                        self.fault = None;
+                        format!("loaded slot {}", slot)
+                    },
+                    Err(e) => format!("could not load slot {}: {}", slot, e)
+                }
+            };
+            self.frontend.show_message(&message);
+        }
+    }
+
+    /// Saves a screenshot next to the ROM for each of `requests`, reporting the file name through the frontend.
+    fn handle_screenshots(&mut self, chip8: &Chip8, requests: &[ImageFormat]) {
+        let Some(rom) = &self.options.rom else {
+            return;
+        };
+        for format in requests {
+            let path = screenshot::next_path(rom, format.name());
+            let message = match screenshot::save(chip8.pixels(), &path, &self.options.palette, self.options.screenshot_scale) {
+                Ok(()) => format!("saved {}", path.file_name().unwrap_or_default().to_string_lossy()),
+                Err(e) => format!("could not save screenshot: {}", e)
+            };
+            self.frontend.show_message(&message);
+        }
+    }
+
+    /// Starts recording a GIF next to the ROM, or finishes the one being recorded.
+    fn toggle_gif(&mut self) {
+        let message = match self.gif.take() {
+            Some(recorder) => match recorder.finish() {
+                Ok(path) => format!("saved {}", path.file_name().unwrap_or_default().to_string_lossy()),
+                Err(e) => format!("could not save GIF: {}", e)
+            },
+            None => {
+                let Some(rom) = &self.options.rom else {
+                    return;
+                };
+                let path = screenshot::next_path(rom, "gif");
+                match GifRecorder::create(&path, &self.options.palette, self.options.gif_scale) {
+                    Ok(recorder) => {
+                        self.gif = Some(recorder);
+                        format!("recording {}", path.file_name().unwrap_or_default().to_string_lossy())
+                    },
+                    Err(e) => format!("could not record GIF: {}", e)
+                }
+            }
+        };
+        self.frontend.show_message(&message);
+    }
+
+    /// Adds the screen to the GIF being recorded. If writing fails, recording stops.
+    fn record_gif_frame(&mut self, chip8: &Chip8) {
+        let Some(recorder) = &mut self.gif else {
+            return;
+        };
+        if let Err(e) = recorder.record(chip8.pixels()) {
+            self.frontend.show_message(&format!("could not record GIF: {}", e));
+            self.gif = None;
+        }
+    }
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    let _ = io::stdout().flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, env, fs};
+
+    use super::*;
+    use crate::platform::Platform;
+
+    /// Plays back a script of inputs, one per poll, and quits when it runs out.
+    #[derive(Default)]
+    struct MockFrontend {
+        script: VecDeque<Input>,
+        polls: usize,
+        presented: Vec<Vec<Vec<u8>>>,
+        messages: Vec<String>
+    }
+
+    impl MockFrontend {
+        fn new(script: impl IntoIterator<Item = Input>) -> Self {
+            MockFrontend { script: script.into_iter().collect(), ..Default::default() }
+        }
+    }
+
+    impl Frontend for MockFrontend {
+        fn present(&mut self, pixels: &[Vec<u8>]) {
+            self.presented.push(pixels.to_vec());
+        }
+
+        fn play_sound(&mut self, _sound: Option<Sound>) {}
+
+        fn poll_input(&mut self) -> Input {
+            self.polls += 1;
+            self.script.pop_front().unwrap_or(Input { quit: true, ..Default::default() })
+        }
+
+        fn show_message(&mut self, message: &str) {
+            self.messages.push(message.to_string());
+        }
+    }
+
+    /// `count` polls with `keys` held. Long enough to span a few frames whatever the polls line up with.
+    fn held(keys: &[usize], count: usize) -> Vec<Input> {
+        let mut input = Input::default();
+        for key in keys {
+            input.keys[*key] = true;
+        }
+        vec![input; count]
+    }
+
+    fn run(program: &[u8], frontend: MockFrontend, options: RunnerOptions) -> (Chip8, MockFrontend, Result<(), Chip8Error>) {
+        let profile = Platform::CosmacVip.profile();
+        let mut chip8 = Chip8::from_profile(&profile);
+        chip8.load_bytes(program).unwrap();
+        let mut runner = Runner::new(frontend, &profile, RunnerOptions { rewind_seconds: 0, ..options });
+        let result = runner.run(&mut chip8);
+        (chip8, runner.frontend, result)
+    }
+
+    // LD V0, 5, then V2 counts presses of key 5: loop: SKP V0, JP loop, ADD V2, 1, wait: SKNP V0, JP wait, JP loop
+    const COUNT_PRESSES: [u8; 14] = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x72, 0x01, 0xE0, 0xA1, 0x12, 0x08, 0x12, 0x02];
+
+    #[test]
+    fn passes_the_keypad_through() {
+        let script = [held(&[5], 8), held(&[], 8), held(&[5, 6], 8)].concat();
+        let (chip8, _, result) = run(&COUNT_PRESSES, MockFrontend::new(script), RunnerOptions::default());
+        assert!(result.is_ok());
+        assert_eq!(chip8.registers()[2], 2);
+        // The last poll quit, with nothing held
+        assert_eq!(chip8.keys(), &[false; 16]);
+    }
+
+    #[test]
+    fn quitting_stops_the_loop() {
+        let quit = Input { quit: true, ..Default::default() };
+        let (_, frontend, result) = run(&COUNT_PRESSES, MockFrontend::new([Input::default(), quit, Input::default()]), RunnerOptions::default());
+        assert!(result.is_ok());
+        assert_eq!(frontend.polls, 2);
+    }
+
+    #[test]
+    fn loads_a_saved_slot() {
+        let dir = env::temp_dir().join(format!("chip8-runner-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("count.ch8");
+        let slot = |request| Input { slot_requests: vec![request], ..Default::default() };
+        let script = [
+            held(&[5], 8), held(&[], 8), vec![slot(SlotRequest::Save(1))],
+            held(&[5], 8), held(&[], 8), held(&[5], 8), held(&[], 8), vec![slot(SlotRequest::Load(1))], held(&[], 8)
+        ].concat();
+        let options = RunnerOptions { rom: Some(rom.clone()), ..Default::default() };
+        let (chip8, frontend, result) = run(&COUNT_PRESSES, MockFrontend::new(script), options);
+        let saved = fs::read(savestate::slot_path(&rom, 1));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(result.is_ok());
+        assert_eq!(frontend.messages, ["saved slot 1", "loaded slot 1"]);
+        assert_eq!(savestate::decode(&saved.unwrap()).unwrap().registers[2], 1);
+        // Three presses, but the last two were undone by the load
+        assert_eq!(chip8.registers()[2], 1);
+    }
+
+    #[test]
+    fn reports_faults() {
+        let (chip8, frontend, result) = run(&[0x60, 0x01, 0xFF, 0xFF], MockFrontend::new(held(&[], 4)), RunnerOptions::default());
+        assert!(matches!(result, Err(Chip8Error::UnknownOpcode { opcode: 0xFFFF, address: 0x202 })));
+        assert_eq!(frontend.messages, ["unknown opcode FFFF at 202"]);
+        // Nothing more runs after the fault
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn presents_the_screen_every_frame() {
+        // LD I, dot, DRW V0, V0, 1, LD I, blank, then V1 counts frames: loop: DRW V0, V0, 1, ADD V1, 1, JP loop
+        let program = [0xA2, 0x0E, 0xD0, 0x01, 0xA2, 0x0F, 0xD0, 0x01, 0x71, 0x01, 0x12, 0x06, 0x00, 0x00, 0x80, 0x00];
+        let options = RunnerOptions { loops_per_second: FRAMES_PER_SECOND, ..Default::default() };
+        let (chip8, frontend, _) = run(&program, MockFrontend::new(held(&[], 30)), options);
+
+        // Redraws keep time with frames, though a host stall can run several frames before the next one
+        let frames = chip8.registers()[1] as usize + 1;
+        assert!(!frontend.presented.is_empty() && frontend.presented.len() <= frames + 1, "{} redraws for {} frames", frontend.presented.len(), frames);
+        assert!(frontend.presented.len() <= frontend.polls);
+        for pixels in &frontend.presented {
+            assert_eq!(pixels, chip8.pixels());
+        }
+        assert_eq!(chip8.pixels()[0][0], 1);
+    }
+}
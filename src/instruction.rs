@@ -0,0 +1,359 @@
+use std::fmt;
+
+/// The extensions to the original CHIP-8, each a superset of the one before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstructionSet {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+/// A decoded opcode. `x` and `y` are register numbers, `n`, `nn` and `nnn` are the 4, 8 and 12 bit immediates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN - Call a machine code routine. Ignored.
+    Sys { nnn: u16 },
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump { nnn: u16 },
+    /// 2NNN
+    Call { nnn: u16 },
+    /// 3XNN - Skip if VX == NN
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4XNN - Skip if VX != NN
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5XY0 - Skip if VX == VY
+    SkipEqReg { x: u8, y: u8 },
+    /// 6XNN - VX = NN
+    LoadImm { x: u8, nn: u8 },
+    /// 7XNN - VX += NN, without touching VF
+    AddImm { x: u8, nn: u8 },
+    /// 8XY0 - VX = VY
+    Move { x: u8, y: u8 },
+    /// 8XY1 - VX |= VY
+    Or { x: u8, y: u8 },
+    /// 8XY2 - VX &= VY
+    And { x: u8, y: u8 },
+    /// 8XY3 - VX ^= VY
+    Xor { x: u8, y: u8 },
+    /// 8XY4 - VX += VY, VF = carry
+    AddReg { x: u8, y: u8 },
+    /// 8XY5 - VX -= VY, VF = not borrow
+    Sub { x: u8, y: u8 },
+    /// 8XY6 - VX >>= 1, VF = bit shifted out
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7 - VX = VY - VX, VF = not borrow
+    SubReverse { x: u8, y: u8 },
+    /// 8XYE - VX <<= 1, VF = bit shifted out
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0 - Skip if VX != VY
+    SkipNeReg { x: u8, y: u8 },
+    /// ANNN - I = NNN
+    LoadIndex { nnn: u16 },
+    /// BNNN - Jump to NNN plus V0 (or VX, see `Quirks::jump_uses_vx`)
+    JumpOffset { nnn: u16 },
+    /// CXNN - VX = random & NN
+    Random { x: u8, nn: u8 },
+    /// DXYN - Draw the N byte sprite at I to (VX, VY)
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E - Skip if the key in VX is held
+    SkipKey { x: u8 },
+    /// EXA1 - Skip if the key in VX is not held
+    SkipNotKey { x: u8 },
+    /// FX07 - VX = delay timer
+    LoadDelay { x: u8 },
+    /// FX0A - Wait for a key and put it in VX
+    WaitKey { x: u8 },
+    /// FX15 - Delay timer = VX
+    SetDelay { x: u8 },
+    /// FX18 - Sound timer = VX
+    SetSound { x: u8 },
+    /// FX1E - I += VX
+    AddIndex { x: u8 },
+    /// FX29 - I = address of the font character in VX
+    LoadFont { x: u8 },
+    /// FX33 - Store the decimal digits of VX at I, I + 1 and I + 2
+    Bcd { x: u8 },
+    /// FX55 - Store V0 to VX at I
+    Store { x: u8 },
+    /// FX65 - Load V0 to VX from I
+    Load { x: u8 },
+
+    // SUPER-CHIP
+    /// 00CN - Scroll the screen down N pixels
+    ScrollDown { n: u8 },
+    /// 00FB - Scroll the screen right 4 pixels
+    ScrollRight,
+    /// 00FC - Scroll the screen left 4 pixels
+    ScrollLeft,
+    /// 00FD - Stop the interpreter
+    Exit,
+    /// 00FE - Switch to 64x32
+    LowRes,
+    /// 00FF - Switch to 128x64
+    HighRes,
+    /// FX30 - I = address of the large font character in VX
+    LoadBigFont { x: u8 },
+    /// FX75 - Save V0 to VX in the RPL user flags
+    SaveFlags { x: u8 },
+    /// FX85 - Load V0 to VX from the RPL user flags
+    LoadFlags { x: u8 },
+
+    // XO-CHIP
+    /// 00DN - Scroll the screen up N pixels
+    ScrollUp { n: u8 },
+    /// 5XY2 - Store VX to VY at I
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3 - Load VX to VY from I
+    LoadRange { x: u8, y: u8 },
+    /// F000 NNNN - I = NNNN. The address is the word after the opcode, so this instruction is 4 bytes long
+    LoadLongIndex,
+    /// FN01 - Draw to and clear the bitplanes in mask N
+    SelectPlanes { n: u8 },
+    /// F002 - Load the 16 byte audio pattern at I
+    LoadAudio,
+    /// FX3A - Pitch register = VX
+    SetPitch { x: u8 },
+    /// Anything else. Holds the raw opcode.
+    Unknown(u16)
+}
+
+/// Splits an opcode into its fields and works out which instruction it is.
+pub fn decode(opcode: u16) -> Instruction {
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    match (opcode & 0xF000) >> 12 {
+        0x0 => match opcode {
+            0x00E0 => Instruction::Clear,
+            0x00EE => Instruction::Return,
+            0x00C0..=0x00CF => Instruction::ScrollDown { n },
+            0x00D0..=0x00DF => Instruction::ScrollUp { n },
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowRes,
+            0x00FF => Instruction::HighRes,
+            _ => Instruction::Sys { nnn }
+        },
+        0x1 => Instruction::Jump { nnn },
+        0x2 => Instruction::Call { nnn },
+        0x3 => Instruction::SkipEqImm { x, nn },
+        0x4 => Instruction::SkipNeImm { x, nn },
+        0x5 => match n {
+            0x0 => Instruction::SkipEqReg { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => Instruction::Unknown(opcode)
+        },
+        0x6 => Instruction::LoadImm { x, nn },
+        0x7 => Instruction::AddImm { x, nn },
+        0x8 => match n {
+            0x0 => Instruction::Move { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubReverse { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => Instruction::Unknown(opcode)
+        },
+        0x9 if n == 0 => Instruction::SkipNeReg { x, y },
+        0xA => Instruction::LoadIndex { nnn },
+        0xB => Instruction::JumpOffset { nnn },
+        0xC => Instruction::Random { x, nn },
+        0xD => Instruction::Draw { x, y, n },
+        0xE => match nn {
+            0x9E => Instruction::SkipKey { x },
+            0xA1 => Instruction::SkipNotKey { x },
+            _ => Instruction::Unknown(opcode)
+        },
+        0xF => match nn {
+            0x00 if x == 0 => Instruction::LoadLongIndex,
+            0x01 => Instruction::SelectPlanes { n: x },
+            0x02 if x == 0 => Instruction::LoadAudio,
+            0x07 => Instruction::LoadDelay { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelay { x },
+            0x18 => Instruction::SetSound { x },
+            0x1E => Instruction::AddIndex { x },
+            0x29 => Instruction::LoadFont { x },
+            0x30 => Instruction::LoadBigFont { x },
+            0x33 => Instruction::Bcd { x },
+            0x3A => Instruction::SetPitch { x },
+            0x55 => Instruction::Store { x },
+            0x65 => Instruction::Load { x },
+            0x75 => Instruction::SaveFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => Instruction::Unknown(opcode)
+        },
+        _ => Instruction::Unknown(opcode)
+    }
+}
+
+/// Turns an instruction back into its opcode. For `LoadLongIndex` this is only the first word; the
+/// address follows it.
+pub fn encode(instruction: &Instruction) -> u16 {
+    let xy = |op: u16, x: u8, y: u8, n: u16| op << 12 | (x as u16) << 8 | (y as u16) << 4 | n;
+    let xnn = |op: u16, x: u8, nn: u8| op << 12 | (x as u16) << 8 | nn as u16;
+
+    match *instruction {
+        Instruction::Sys { nnn } => nnn & 0x0FFF,
+        Instruction::Clear => 0x00E0,
+        Instruction::Return => 0x00EE,
+        Instruction::Jump { nnn } => 0x1000 | nnn,
+        Instruction::Call { nnn } => 0x2000 | nnn,
+        Instruction::SkipEqImm { x, nn } => xnn(0x3, x, nn),
+        Instruction::SkipNeImm { x, nn } => xnn(0x4, x, nn),
+        Instruction::SkipEqReg { x, y } => xy(0x5, x, y, 0x0),
+        Instruction::LoadImm { x, nn } => xnn(0x6, x, nn),
+        Instruction::AddImm { x, nn } => xnn(0x7, x, nn),
+        Instruction::Move { x, y } => xy(0x8, x, y, 0x0),
+        Instruction::Or { x, y } => xy(0x8, x, y, 0x1),
+        Instruction::And { x, y } => xy(0x8, x, y, 0x2),
+        Instruction::Xor { x, y } => xy(0x8, x, y, 0x3),
+        Instruction::AddReg { x, y } => xy(0x8, x, y, 0x4),
+        Instruction::Sub { x, y } => xy(0x8, x, y, 0x5),
+        Instruction::ShiftRight { x, y } => xy(0x8, x, y, 0x6),
+        Instruction::SubReverse { x, y } => xy(0x8, x, y, 0x7),
+        Instruction::ShiftLeft { x, y } => xy(0x8, x, y, 0xE),
+        Instruction::SkipNeReg { x, y } => xy(0x9, x, y, 0x0),
+        Instruction::LoadIndex { nnn } => 0xA000 | nnn,
+        Instruction::JumpOffset { nnn } => 0xB000 | nnn,
+        Instruction::Random { x, nn } => xnn(0xC, x, nn),
+        Instruction::Draw { x, y, n } => xy(0xD, x, y, n as u16),
+        Instruction::SkipKey { x } => xnn(0xE, x, 0x9E),
+        Instruction::SkipNotKey { x } => xnn(0xE, x, 0xA1),
+        Instruction::LoadDelay { x } => xnn(0xF, x, 0x07),
+        Instruction::WaitKey { x } => xnn(0xF, x, 0x0A),
+        Instruction::SetDelay { x } => xnn(0xF, x, 0x15),
+        Instruction::SetSound { x } => xnn(0xF, x, 0x18),
+        Instruction::AddIndex { x } => xnn(0xF, x, 0x1E),
+        Instruction::LoadFont { x } => xnn(0xF, x, 0x29),
+        Instruction::Bcd { x } => xnn(0xF, x, 0x33),
+        Instruction::Store { x } => xnn(0xF, x, 0x55),
+        Instruction::Load { x } => xnn(0xF, x, 0x65),
+        Instruction::ScrollDown { n } => 0x00C0 | n as u16,
+        Instruction::ScrollRight => 0x00FB,
+        Instruction::ScrollLeft => 0x00FC,
+        Instruction::Exit => 0x00FD,
+        Instruction::LowRes => 0x00FE,
+        Instruction::HighRes => 0x00FF,
+        Instruction::LoadBigFont { x } => xnn(0xF, x, 0x30),
+        Instruction::SaveFlags { x } => xnn(0xF, x, 0x75),
+        Instruction::LoadFlags { x } => xnn(0xF, x, 0x85),
+        Instruction::ScrollUp { n } => 0x00D0 | n as u16,
+        Instruction::SaveRange { x, y } => xy(0x5, x, y, 0x2),
+        Instruction::LoadRange { x, y } => xy(0x5, x, y, 0x3),
+        Instruction::LoadLongIndex => 0xF000,
+        Instruction::SelectPlanes { n } => xnn(0xF, n, 0x01),
+        Instruction::LoadAudio => 0xF002,
+        Instruction::SetPitch { x } => xnn(0xF, x, 0x3A),
+        Instruction::Unknown(opcode) => opcode
+    }
+}
+
+impl Instruction {
+    /// Where a jump or call goes, if the instruction is one. BNNN has no fixed target, as it adds a register
+    /// to NNN.
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Instruction::Jump { nnn } | Instruction::Call { nnn } => Some(*nnn),
+            _ => None
+        }
+    }
+
+    /// The first instruction set that has the instruction. DXY0 is SUPER-CHIP's 16x16 sprite, though it is a
+    /// valid CHIP-8 instruction that draws nothing.
+    pub fn instruction_set(&self) -> InstructionSet {
+        match self {
+            Instruction::ScrollDown { .. } | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Exit
+                | Instruction::LowRes | Instruction::HighRes | Instruction::LoadBigFont { .. }
+                | Instruction::SaveFlags { .. } | Instruction::LoadFlags { .. } | Instruction::Draw { n: 0, .. } => InstructionSet::SuperChip,
+            Instruction::ScrollUp { .. } | Instruction::SaveRange { .. } | Instruction::LoadRange { .. }
+                | Instruction::LoadLongIndex | Instruction::SelectPlanes { .. } | Instruction::LoadAudio
+                | Instruction::SetPitch { .. } => InstructionSet::XoChip,
+            _ => InstructionSet::Chip8
+        }
+    }
+
+    /// Number of bytes the instruction takes up in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LoadLongIndex => 4,
+            _ => 2
+        }
+    }
+
+    /// Writes the mnemonic, using `address` to name jump and call targets.
+    pub fn write_mnemonic(&self, f: &mut dyn fmt::Write, address: &dyn Fn(u16) -> String) -> fmt::Result {
+        match *self {
+            Instruction::Sys { nnn } => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump { nnn } => write!(f, "JP {}", address(nnn)),
+            Instruction::Call { nnn } => write!(f, "CALL {}", address(nnn)),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Move { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpOffset { nnn } => write!(f, "JP V0, {}", address(nnn)),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::Bcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::Store { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::SaveRange { x, y } => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Instruction::LoadLongIndex => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode)
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_mnemonic(f, &|address| format!("0x{:03X}", address))
+    }
+}
@@ -0,0 +1,26 @@
+pub mod font;
+pub mod chip8;
+pub mod error;
+pub mod instruction;
+pub mod disassembler;
+pub mod assembler;
+pub mod debugger;
+pub mod trace;
+pub mod tracediff;
+pub mod quirks;
+pub mod rng;
+pub mod audio;
+pub mod savestate;
+pub mod rewind;
+pub mod clock;
+pub mod movie;
+pub mod platform;
+pub mod config;
+pub mod frontend;
+pub mod terminal;
+pub mod screenshot;
+pub mod recording;
+#[cfg(feature = "sdl")]
+pub mod sdl;
+#[cfg(feature = "tui")]
+pub mod tui;
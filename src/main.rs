@@ -1,8 +1,472 @@
-pub mod font;
-pub mod chip8;
+mod cli;
+
+use std::{fs::File, io::{self, BufRead, BufWriter, Write}, path::Path, process::exit};
+
+use chip_8::{chip8::Chip8, clock::InstructionBudget, config::GIF_SCALE, debugger::{self, Debugger}, movie::{self, Movie}, recording::GifRecorder, rng::Rng, savestate, screenshot, trace::{TraceFilter, TraceRecord, Tracer}, tracediff::{self, InputScript, Outcome}};
+use cli::{Command, Options, TraceDiffOptions};
 
 fn main() {
-    let mut chip8 = chip8::Chip8::new();
-    chip8.load_rom(String::from("5-quirks.ch8"));
-    chip8.start();
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(Command::Run(options)) => *options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        },
+        Ok(Command::Disassemble(rom)) => {
+            disassemble(&rom);
+            return;
+        },
+        Ok(Command::Assemble { source, output }) => {
+            assemble(&source, &output);
+            return;
+        },
+        Ok(Command::TraceDiff(options)) => {
+            trace_diff(&options);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            exit(2);
+        }
+    };
+
+    let mut chip8 = Chip8::from_profile(&options.profile);
+    if let Err(e) = chip8.load_rom(&options.rom) {
+        eprintln!("error: could not load {}: {}", options.rom.display(), e);
+        exit(1);
+    }
+    chip8.set_rng(match options.seed {
+        Some(seed) => Rng::new(options.rng, seed),
+        None => Rng::from_entropy(options.rng)
+    });
+    if let Some(path) = &options.load_state {
+        if let Err(e) = savestate::load(&mut chip8, path) {
+            eprintln!("error: could not load state from {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+    let movie = options.play_movie.as_ref().map(|path| play_movie(&mut chip8, &options, path));
+    if let Some(path) = &options.trace {
+        match File::create(path) {
+            Ok(file) => {
+                let tracer = Tracer::new(Box::new(BufWriter::new(file)), options.trace_format, options.trace_filter.clone());
+                chip8.set_tracer(Some(tracer));
+            },
+            Err(e) => {
+                eprintln!("error: could not create {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+    }
+
+    match (options.headless, debugger(&options)) {
+        (true, Some(debugger)) => debug_headless(&mut chip8, &options, debugger, movie.as_ref()),
+        (true, None) => run_headless(&mut chip8, &options, movie.as_ref()),
+        (false, debugger) if options.tui => run_terminal(&mut chip8, &options, debugger, movie),
+        (false, debugger) => run_window(&mut chip8, &options, debugger, movie)
+    }
+}
+
+/// Loads the movie at `path` and sets `chip8` up the way it was when the movie was recorded.
+fn play_movie(chip8: &mut Chip8, options: &Options, path: &Path) -> Movie {
+    let movie = match Movie::load(path) {
+        Ok(movie) => movie,
+        Err(e) => {
+            eprintln!("error: could not load movie {}: {}", path.display(), e);
+            exit(1);
+        }
+    };
+    // The platform decides the memory, font and instructions, which are fixed before the movie is loaded
+    if movie.platform != options.profile.platform {
+        let flag = movie.platform.map_or_else(|| String::from("without --platform"), |platform| format!("with --platform {}", platform));
+        eprintln!("error: {} was recorded on a different platform, play it {}", path.display(), flag);
+        exit(1);
+    }
+    let rom_matches = std::fs::read(&options.rom).is_ok_and(|rom| movie::rom_hash(&rom) == movie.rom_hash);
+    if !rom_matches {
+        eprintln!("warning: {} was recorded with a different ROM, so it probably won't play back properly", path.display());
+    }
+    movie.prepare(chip8);
+    movie
+}
+
+fn instruction_budget(options: &Options, movie: Option<&Movie>) -> InstructionBudget {
+    match movie {
+        Some(movie) => movie.instruction_budget(),
+        None => options.profile.instruction_budget()
+    }
+}
+
+/// A debugger is only needed for --debug and --write-log. Plain runs skip its bookkeeping.
+fn debugger(options: &Options) -> Option<Debugger> {
+    if !options.debug && options.write_log.is_none() {
+        return None;
+    }
+    let mut debugger = Debugger::new();
+    if let Some(path) = &options.write_log {
+        match File::create(path) {
+            Ok(file) => debugger.set_write_log(Some(Box::new(BufWriter::new(file)))),
+            Err(e) => {
+                eprintln!("error: could not create {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+    }
+    if options.debug {
+        debugger.pause();
+    }
+    Some(debugger)
+}
+
+fn disassemble(rom: &Path) {
+    let rom_data = match std::fs::read(rom) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("error: could not load {}: {}", rom.display(), e);
+            exit(1);
+        }
+    };
+    let lines = match chip_8::disassembler::disassemble(&rom_data) {
+        Ok(lines) => lines,
+        Err(e) => {
+            eprintln!("error: could not disassemble {}: {}", rom.display(), e);
+            exit(1);
+        }
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+fn assemble(source: &Path, output: &Path) {
+    let rom = match chip_8::assembler::assemble_file(source) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+    if let Err(e) = std::fs::write(output, &rom) {
+        eprintln!("error: could not write {}: {}", output.display(), e);
+        exit(1);
+    }
+}
+
+/// Runs the ROM against a reference trace and reports the first instruction where they differ.
+fn trace_diff(options: &TraceDiffOptions) {
+    let mut chip8 = Chip8::from_profile(&options.profile);
+    if let Err(e) = chip8.load_rom(&options.rom) {
+        eprintln!("error: could not load {}: {}", options.rom.display(), e);
+        exit(1);
+    }
+    chip8.set_rng(Rng::new(options.rng, options.seed));
+    if let Some(path) = &options.trace {
+        match File::create(path) {
+            Ok(file) => chip8.set_tracer(Some(Tracer::new(Box::new(BufWriter::new(file)), options.trace_format, TraceFilter::default()))),
+            Err(e) => {
+                eprintln!("error: could not create {}: {}", path.display(), e);
+                exit(1);
+            }
+        }
+    }
+    let input = match &options.input {
+        Some(path) => match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| InputScript::parse(&text)) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("error: could not read {}: {}", path.display(), e);
+                exit(1);
+            }
+        },
+        None => InputScript::default()
+    };
+    let reference = match File::open(&options.reference) {
+        Ok(file) => io::BufReader::new(file),
+        Err(e) => {
+            eprintln!("error: could not open {}: {}", options.reference.display(), e);
+            exit(1);
+        }
+    };
+
+    // Blank lines and # comments are skipped, so reference traces can be annotated
+    let records = reference.lines().enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty() && !line.starts_with('#')))
+        .map(|(number, line)| {
+            let line = line.map_err(|e| e.to_string())?;
+            line.parse::<TraceRecord>().map_err(|e| format!("{} line {}: {}", options.reference.display(), number + 1, e))
+        });
+    let outcome = tracediff::compare(&mut chip8, records, &input, options.profile.instruction_budget());
+    let _ = chip8.flush_trace();
+    match outcome {
+        Ok(Outcome::Matched { steps }) => println!("All {} instructions match", steps),
+        Ok(Outcome::Diverged(divergence)) => {
+            println!("{}\n\n{}", divergence.report(), debugger::status(&chip8));
+            exit(1);
+        },
+        Ok(Outcome::Fault { step, error }) => {
+            println!("Program faulted after {} matching instructions: {}\n\n{}", step, error, debugger::status(&chip8));
+            exit(1);
+        },
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Runs the requested number of frames, or the whole movie, as fast as possible and prints the screen.
+fn run_headless(chip8: &mut Chip8, options: &Options, movie: Option<&Movie>) {
+    let mut instructions = instruction_budget(options, movie);
+    let frames = options.frames.or(movie.map(|movie| movie.len() as u64)).unwrap_or(0);
+    let mut gif = gif_recorder(options);
+    for frame in 0..frames {
+        if chip8.has_exited() {
+            break;
+        }
+        // Keys are let go once the movie runs out, like in a window
+        if let Some(movie) = movie {
+            chip8.set_keys(movie.keys(frame as usize).unwrap_or([false; 16]));
+        }
+        if let Err(e) = chip8.run_frame(instructions.next_frame()) {
+            eprintln!("error: {}", e);
+            print_screen(chip8);
+            save_screenshot(chip8, options);
+            finish_gif(gif);
+            let _ = chip8.flush_trace();
+            exit(1);
+        }
+        record_gif_frame(&mut gif, chip8);
+    }
+    print_screen(chip8);
+    save_screenshot(chip8, options);
+    finish_gif(gif);
+    save_state(chip8, options);
+}
+
+/// Like `run_headless`, but under `debugger`. With --debug it starts paused and takes debugger commands from
+/// stdin, and without --frames it runs until the user quits or stdin is closed.
+fn debug_headless(chip8: &mut Chip8, options: &Options, mut debugger: Debugger, movie: Option<&Movie>) {
+    let mut instructions = instruction_budget(options, movie);
+    let limit = options.frames.or(movie.map(|movie| movie.len() as u64));
+    if options.debug {
+        println!("{}", debugger::status(chip8));
+    }
+
+    let mut lines = io::stdin().lock().lines();
+    let mut frames = 0;
+    let mut gif = gif_recorder(options);
+    while !debugger.wants_quit() {
+        if debugger.is_paused() {
+            print!("(chip8) ");
+            let _ = io::stdout().flush();
+            let Some(Ok(line)) = lines.next() else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match debugger::parse_command(&line) {
+                Ok(command) => println!("{}", debugger.handle(chip8, command)),
+                Err(e) => println!("error: {}", e)
+            }
+        } else if limit.is_some_and(|limit| frames >= limit) || chip8.has_exited() {
+            break;
+        } else {
+            if let Some(movie) = movie {
+                chip8.set_keys(movie.keys(frames as usize).unwrap_or([false; 16]));
+            }
+            frames += 1;
+            match debugger.run_frame(chip8, instructions.next_frame()) {
+                Some(message) if options.debug => println!("{}\n{}", message, debugger::status(chip8)),
+                // Without --debug the only way to stop is a fault
+                Some(message) => {
+                    eprintln!("error: {}", message);
+                    print_screen(chip8);
+                    save_screenshot(chip8, options);
+                    finish_gif(gif);
+                    let _ = chip8.flush_trace();
+                    exit(1);
+                },
+                None => {}
+            }
+            record_gif_frame(&mut gif, chip8);
+        }
+    }
+    print_screen(chip8);
+    save_screenshot(chip8, options);
+    finish_gif(gif);
+    save_state(chip8, options);
+}
+
+fn save_state(chip8: &Chip8, options: &Options) {
+    if let Some(path) = &options.save_state {
+        if let Err(e) = savestate::save(chip8, path) {
+            eprintln!("error: could not save state to {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+fn save_screenshot(chip8: &Chip8, options: &Options) {
+    if let Some(path) = &options.screenshot {
+        if let Err(e) = screenshot::save(chip8.pixels(), path, &options.palette(), options.screenshot_scale) {
+            eprintln!("error: could not save screenshot to {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// Starts the GIF asked for with --record.
+fn gif_recorder(options: &Options) -> Option<GifRecorder> {
+    let path = options.record.as_ref()?;
+    match GifRecorder::create(path, &options.palette(), GIF_SCALE) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            eprintln!("error: could not create {}: {}", path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// Adds a frame to `gif`. If that fails, the error is printed and recording stops, but the run goes on.
+fn record_gif_frame(gif: &mut Option<GifRecorder>, chip8: &Chip8) {
+    if let Some(Err(e)) = gif.as_mut().map(|recorder| recorder.record(chip8.pixels())) {
+        eprintln!("error: could not record GIF: {}", e);
+        *gif = None;
+    }
+}
+
+fn finish_gif(gif: Option<GifRecorder>) {
+    let Some(recorder) = gif else {
+        return;
+    };
+    let path = recorder.path().to_path_buf();
+    if let Err(e) = recorder.finish() {
+        eprintln!("error: could not save GIF to {}: {}", path.display(), e);
+    }
+}
+
+fn print_screen(chip8: &Chip8) {
+    for row in chip8.pixels() {
+        // Pixels only in XO-CHIP's second plane, or in both, get their own characters
+        let line: String = row.iter().map(|pixel| ['.', '#', '+', '@'][(*pixel & 0b11) as usize]).collect();
+        println!("{}", line);
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_window(chip8: &mut Chip8, options: &Options, debugger: Option<Debugger>, movie: Option<Movie>) {
+    let sdl_options = chip_8::sdl::SdlOptions {
+        scale: options.scale,
+        foreground: options.foreground,
+        background: options.background,
+        plane_2: options.plane_2,
+        plane_3: options.plane_3
+    };
+    match chip_8::sdl::SdlFrontend::new(&options.profile, sdl_options) {
+        Ok(frontend) => run_frontend(frontend, chip8, options, debugger, movie),
+        Err(e) => {
+            eprintln!("error: could not open window: {}", e);
+            exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn run_terminal(chip8: &mut Chip8, options: &Options, debugger: Option<Debugger>, movie: Option<Movie>) {
+    let terminal_options = chip_8::tui::TerminalOptions {
+        glyphs: options.glyphs,
+        foreground: options.foreground,
+        background: options.background,
+        plane_2: options.plane_2,
+        plane_3: options.plane_3
+    };
+    match chip_8::tui::TerminalFrontend::new(terminal_options) {
+        Ok(frontend) => run_frontend(frontend, chip8, options, debugger, movie),
+        Err(e) => {
+            eprintln!("error: could not set up the terminal: {}", e);
+            exit(1);
+        }
+    }
+}
+
+/// Runs `chip8` in real time on `frontend` until the user quits, recording or playing `movie` if asked to.
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn run_frontend<F: chip_8::frontend::Frontend>(frontend: F, chip8: &mut Chip8, options: &Options, debugger: Option<Debugger>, movie: Option<Movie>) {
+    let runner_options = chip_8::frontend::RunnerOptions {
+        loops_per_second: options.loops_per_second,
+        mute: options.mute,
+        rom: Some(options.rom.clone()),
+        rewind_seconds: options.rewind_seconds,
+        rewind_memory: options.rewind_memory,
+        palette: options.palette(),
+        screenshot_scale: options.screenshot_scale,
+        gif_scale: GIF_SCALE
+    };
+    let mut runner = chip_8::frontend::Runner::new(frontend, &options.profile, runner_options);
+    if let Some(recorder) = gif_recorder(options) {
+        runner.record_gif(recorder);
+    }
+    if let Some(movie) = movie {
+        runner.play_movie(movie);
+    }
+    if options.record_movie.is_some() {
+        match std::fs::read(&options.rom) {
+            Ok(rom) => runner.record_movie(Movie::new(&rom, chip8, &options.profile)),
+            Err(e) => {
+                eprintln!("error: could not read {}: {}", options.rom.display(), e);
+                exit(1);
+            }
+        }
+    }
+
+    let result = match debugger {
+        Some(mut debugger) => {
+            let commands = options.debug.then(stdin_lines);
+            runner.run_debugged(chip8, &mut debugger, commands.as_ref());
+            Ok(())
+        },
+        None => runner.run(chip8)
+    };
+
+    // Save the movie even if the program faulted, since that's when it's most useful
+    let recorded = runner.take_movie();
+    let gif = runner.take_gif();
+    // Closes the window or gives the terminal back before any errors are printed
+    drop(runner);
+    finish_gif(gif);
+    if let (Some(path), Some(movie)) = (&options.record_movie, recorded) {
+        if let Err(e) = movie.save(path) {
+            eprintln!("error: could not save movie to {}: {}", path.display(), e);
+        }
+    }
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        let _ = chip8.flush_trace();
+        exit(1);
+    }
+}
+
+/// Reads stdin on its own thread, so the window keeps responding while waiting for debugger commands.
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn stdin_lines() -> std::sync::mpsc::Receiver<String> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_chip8: &mut Chip8, _options: &Options, _debugger: Option<Debugger>, _movie: Option<Movie>) {
+    eprintln!("error: this build has no window. Rebuild with the `sdl` feature or use --headless.");
+    exit(1);
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_terminal(_chip8: &mut Chip8, _options: &Options, _debugger: Option<Debugger>, _movie: Option<Movie>) {
+    eprintln!("error: this build has no terminal frontend. Rebuild with the `tui` feature or use --headless.");
+    exit(1);
 }
@@ -0,0 +1,241 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{chip8::{Chip8, FRAMES_PER_SECOND}, clock::InstructionBudget, platform::{Platform, Profile}, quirks::Quirks, rng::{Rng, RngKind}};
+
+const HEADER: &str = "chip8-movie 1";
+
+/// A day of frames, far more than anyone plays in one sitting, so a bad `*N` can't take all the memory.
+const MAX_FRAMES: usize = 24 * 60 * 60 * FRAMES_PER_SECOND as usize;
+
+/// The keypad state of every frame of a run, plus what else it takes to play the run back exactly: the
+/// platform, the random number generator, the quirks and the speed. Recordings always start from a freshly loaded ROM.
+///
+/// Movies are text, so they can be attached to bug reports and diffed:
+///
+/// ```text
+/// chip8-movie 1
+/// rom 84A1C0FFEE123456
+/// platform vip
+/// rng modern 1234
+/// instructions-per-second 600
+/// quirks shift_uses_vy=on jump_uses_vx=off ...
+/// frames
+/// 0000*120
+/// 0020*8
+/// ```
+///
+/// The platform is `default` for runs without `--platform`. Each frame line is a bitmask of the keys held, bit
+/// `n` for key `n`, with `*N` for `N` frames in a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// `rom_hash` of the ROM it was recorded with
+    pub rom_hash: u64,
+    /// `Profile::platform` of the run
+    pub platform: Option<Platform>,
+    pub rng: Rng,
+    pub quirks: Quirks,
+    pub instructions_per_second: u32,
+    frames: Vec<u16>
+}
+
+impl Movie {
+    /// Starts a recording of `rom` running on `chip8`, which was made from `profile` and must not have run any
+    /// instructions yet.
+    pub fn new(rom: &[u8], chip8: &Chip8, profile: &Profile) -> Self {
+        Movie {
+            rom_hash: rom_hash(rom),
+            platform: profile.platform,
+            rng: chip8.rng(),
+            quirks: chip8.quirks(),
+            instructions_per_second: profile.instructions_per_second,
+            frames: Vec::new()
+        }
+    }
+
+    /// Sets `chip8` up the way it was when recording started.
+    pub fn prepare(&self, chip8: &mut Chip8) {
+        chip8.set_rng(self.rng);
+        chip8.set_quirks(self.quirks);
+    }
+
+    /// The speed the movie was recorded at, from its first frame.
+    pub fn instruction_budget(&self) -> InstructionBudget {
+        InstructionBudget::new(self.instructions_per_second, FRAMES_PER_SECOND)
+    }
+
+    /// Adds the keys held during the next frame.
+    pub fn record(&mut self, keys: [bool; 16]) {
+        let mask = keys.iter().enumerate().fold(0, |mask, (key, held)| mask | (*held as u16) << key);
+        self.frames.push(mask);
+    }
+
+    /// The keys held during `frame`, or `None` once the movie is over.
+    pub fn keys(&self, frame: usize) -> Option<[bool; 16]> {
+        let mask = self.frames.get(frame)?;
+        Some(std::array::from_fn(|key| mask & (1 << key) != 0))
+    }
+
+    /// Number of frames recorded.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        fs::write(path, self.to_string()).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Movie::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(number, line)| (number + 1, line.trim()));
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(format!("not a movie, the first line should be '{}'", HEADER));
+        }
+
+        let (mut rom_hash, mut platform, mut rng, mut instructions_per_second) = (None, None, None, None);
+        let mut quirks = Quirks::default();
+        for (number, line) in lines.by_ref() {
+            let error = |message: &str| format!("line {}: {}", number, message);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {},
+                ["frames"] => break,
+                ["rom", hash] => rom_hash = Some(u64::from_str_radix(hash, 16).map_err(|_| error("bad ROM hash"))?),
+                ["platform", "default"] => platform = Some(None),
+                ["platform", name] => platform = Some(Some(name.parse::<Platform>().map_err(|e| error(&e))?)),
+                ["rng", kind, state] => {
+                    let kind: RngKind = kind.parse().map_err(|e: String| error(&e))?;
+                    rng = Some(Rng::new(kind, state.parse().map_err(|_| error("bad RNG state"))?));
+                },
+                ["instructions-per-second", count] => {
+                    instructions_per_second = Some(count.parse().map_err(|_| error("bad instruction count"))?);
+                },
+                ["quirks", settings @ ..] => {
+                    for setting in settings {
+                        let (name, on) = match setting.split_once('=') {
+                            Some((name, "on")) => (name, true),
+                            Some((name, "off")) => (name, false),
+                            _ => return Err(error(&format!("expected NAME=on|off, got '{}'", setting)))
+                        };
+                        quirks.set(name, on).map_err(|e| error(&e))?;
+                    }
+                },
+                _ => return Err(error(&format!("unexpected '{}'", line)))
+            }
+        }
+
+        let mut frames = Vec::new();
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let (mask, count) = line.split_once('*').unwrap_or((line, "1"));
+            let mask = u16::from_str_radix(mask, 16).map_err(|_| format!("line {}: bad key mask '{}'", number, mask))?;
+            let count: usize = count.parse().map_err(|_| format!("line {}: bad frame count '{}'", number, count))?;
+            if count > MAX_FRAMES - frames.len() {
+                return Err(format!("line {}: the movie is longer than the limit of {} frames", number, MAX_FRAMES));
+            }
+            frames.extend(std::iter::repeat_n(mask, count));
+        }
+
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or("missing rom line")?,
+            platform: platform.ok_or("missing platform line")?,
+            rng: rng.ok_or("missing rng line")?,
+            quirks,
+            instructions_per_second: instructions_per_second.ok_or("missing instructions-per-second line")?,
+            frames
+        })
+    }
+}
+
+impl std::fmt::Display for Movie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016X}", self.rom_hash)?;
+        writeln!(f, "platform {}", self.platform.map_or("default", |platform| platform.name()))?;
+        writeln!(f, "rng {} {}", self.rng.kind(), self.rng.state())?;
+        writeln!(f, "instructions-per-second {}", self.instructions_per_second)?;
+        let mut quirks = String::new();
+        for name in Quirks::NAMES {
+            let _ = write!(quirks, " {}={}", name, if self.quirks.get(name) == Some(true) { "on" } else { "off" });
+        }
+        writeln!(f, "quirks{}", quirks)?;
+        writeln!(f, "frames")?;
+        for run in self.frames.chunk_by(|a, b| a == b) {
+            match run.len() {
+                1 => writeln!(f, "{:04X}", run[0])?,
+                count => writeln!(f, "{:04X}*{}", run[0], count)?
+            }
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a, to notice a movie being played back with a different ROM.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie(platform: Platform) -> Movie {
+        let profile = platform.profile();
+        let mut chip8 = Chip8::from_profile(&profile);
+        chip8.set_rng(Rng::new(RngKind::Modern, 1234));
+        let mut movie = Movie::new(&[0x12, 0x00], &chip8, &profile);
+        for frame in 0..10 {
+            movie.record(std::array::from_fn(|key| key == 5 && frame >= 4));
+        }
+        movie
+    }
+
+    /// `movie`'s text with `line` swapped for `with`.
+    fn edited(movie: &Movie, line: &str, with: &str) -> String {
+        let text = movie.to_string();
+        let start = text.find(line).unwrap();
+        let end = start + text[start..].find('\n').unwrap();
+        format!("{}{}{}", &text[..start], with, &text[end..])
+    }
+
+    #[test]
+    fn round_trips() {
+        let movie = movie(Platform::CosmacVip);
+        let text = movie.to_string();
+        assert!(text.contains("\nplatform vip\n") && text.contains("\n0000*4\n0020*6\n"), "{}", text);
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn runs_without_a_platform_are_default() {
+        let text = edited(&movie(Platform::XoChip), "platform", "platform default");
+        assert_eq!(Movie::parse(&text).unwrap().platform, None);
+        assert!(Movie::parse(&edited(&movie(Platform::XoChip), "platform", "")).unwrap_err().contains("platform"));
+        assert!(Movie::parse(&edited(&movie(Platform::XoChip), "platform", "platform atari")).is_err());
+    }
+
+    #[test]
+    fn quirks_must_be_on_or_off() {
+        let movie = movie(Platform::Chip48);
+        assert!(Movie::parse(&edited(&movie, "quirks", "quirks vf_reset=on wrap_sprites=off")).is_ok());
+        for setting in ["vf_reset=yes", "vf_reset=", "vf_reset", "vf_reset=On"] {
+            let text = edited(&movie, "quirks", &format!("quirks {}", setting));
+            assert!(Movie::parse(&text).unwrap_err().contains("on|off"), "{}", setting);
+        }
+    }
+
+    #[test]
+    fn frame_counts_are_capped() {
+        let text = movie(Platform::Chip48).to_string();
+        assert!(Movie::parse(&format!("{}0000*{}\n", text, MAX_FRAMES - 10)).is_ok());
+        assert!(Movie::parse(&format!("{}0000*{}\n", text, MAX_FRAMES - 9)).unwrap_err().contains("limit"));
+        assert!(Movie::parse(&format!("{}0000*{}\n", text, usize::MAX)).unwrap_err().contains("limit"));
+        assert!(Movie::parse(&format!("{}0000*-1\n", text)).is_err());
+    }
+}
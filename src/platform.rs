@@ -0,0 +1,160 @@
+use std::{fmt, str::FromStr};
+
+use crate::{chip8::{FRAMES_PER_SECOND, MEMORY_SIZE, XO_MEMORY_SIZE}, clock::InstructionBudget, font::Font, instruction::InstructionSet, quirks::Quirks};
+
+/// Everything needed to make the emulator behave like one particular interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// The interpreter this profile is for, or `None` for the default mix of all of them.
+    pub platform: Option<Platform>,
+    pub quirks: Quirks,
+    pub instructions_per_second: u32,
+    /// The largest resolution the interpreter can display, as (width, height).
+    pub screen_size: (usize, usize),
+    pub font: Font,
+    /// Bytes of memory. XO-CHIP has 64 KiB, everything else 4 KiB.
+    pub memory_size: usize,
+    /// Instructions past this set are unknown opcodes.
+    pub instruction_set: InstructionSet
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            platform: None,
+            quirks: Quirks::default(),
+            instructions_per_second: 2600,
+            screen_size: (64, 32),
+            font: Font::Chip48,
+            memory_size: MEMORY_SIZE,
+            instruction_set: InstructionSet::XoChip
+        }
+    }
+}
+
+impl Profile {
+    /// Splits `instructions_per_second` into the `FRAMES_PER_SECOND` frames.
+    pub fn instruction_budget(&self) -> InstructionBudget {
+        InstructionBudget::new(self.instructions_per_second, FRAMES_PER_SECOND)
+    }
+}
+
+/// Historical interpreters with well known behaviour.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [Platform::CosmacVip, Platform::Chip48, Platform::SuperChip, Platform::XoChip];
+
+    /// The name used to select the platform on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip"
+        }
+    }
+
+    pub fn profile(&self) -> Profile {
+        match self {
+            Platform::CosmacVip => Profile {
+                platform: Some(*self),
+                quirks: Quirks {
+                    shift_uses_vy: true,
+                    jump_uses_vx: false,
+                    index_increments: true,
+                    wrap_sprites: false,
+                    vf_reset: true,
+                    display_wait: true,
+                    index_overflow_sets_vf: false,
+                    collision_counts_rows: false
+                },
+                instructions_per_second: 600,
+                screen_size: (64, 32),
+                font: Font::CosmacVip,
+                memory_size: MEMORY_SIZE,
+                instruction_set: InstructionSet::Chip8
+            },
+            Platform::Chip48 => Profile {
+                platform: Some(*self),
+                quirks: Quirks {
+                    shift_uses_vy: false,
+                    jump_uses_vx: true,
+                    index_increments: false,
+                    wrap_sprites: false,
+                    vf_reset: false,
+                    display_wait: false,
+                    index_overflow_sets_vf: false,
+                    collision_counts_rows: false
+                },
+                instructions_per_second: 1200,
+                screen_size: (64, 32),
+                font: Font::Chip48,
+                memory_size: MEMORY_SIZE,
+                instruction_set: InstructionSet::Chip8
+            },
+            Platform::SuperChip => Profile {
+                platform: Some(*self),
+                quirks: Quirks {
+                    shift_uses_vy: false,
+                    jump_uses_vx: true,
+                    index_increments: false,
+                    wrap_sprites: false,
+                    vf_reset: false,
+                    display_wait: false,
+                    index_overflow_sets_vf: false,
+                    collision_counts_rows: true
+                },
+                instructions_per_second: 1800,
+                screen_size: (128, 64),
+                font: Font::Chip48,
+                memory_size: MEMORY_SIZE,
+                instruction_set: InstructionSet::SuperChip
+            },
+            Platform::XoChip => Profile {
+                platform: Some(*self),
+                quirks: Quirks {
+                    shift_uses_vy: true,
+                    jump_uses_vx: false,
+                    index_increments: true,
+                    wrap_sprites: true,
+                    vf_reset: false,
+                    display_wait: false,
+                    index_overflow_sets_vf: false,
+                    collision_counts_rows: false
+                },
+                instructions_per_second: 60000,
+                screen_size: (128, 64),
+                font: Font::Chip48,
+                memory_size: XO_MEMORY_SIZE,
+                instruction_set: InstructionSet::XoChip
+            }
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL.iter()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = Platform::ALL.iter().map(|platform| platform.name()).collect();
+                format!("unknown platform '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
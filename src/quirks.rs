@@ -0,0 +1,83 @@
+/// Behaviour that differs between chip8 interpreters. Every ambiguous instruction reads its
+/// flag from here, so a game can be given the interpreter it was written for at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE copy VY into VX before shifting. Otherwise VX is shifted in place.
+    pub shift_uses_vy: bool,
+    /// BNNN jumps to NNN + VX, X being the high nibble of NNN. Otherwise it jumps to NNN + V0.
+    pub jump_uses_vx: bool,
+    /// FX55/FX65 leave I pointing one past the last register stored or loaded.
+    pub index_increments: bool,
+    /// Sprites drawn past the edge of the screen wrap to the other side. Otherwise they clip.
+    pub wrap_sprites: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// DXYN waits for the start of the next frame before drawing, so only one sprite is drawn per frame.
+    pub display_wait: bool,
+    /// FX1E sets VF to 1 when I goes past the end of addressable memory.
+    pub index_overflow_sets_vf: bool,
+    /// In high resolution, DXYN sets VF to the number of sprite rows that collided or were clipped off the
+    /// bottom of the screen, like SUPER-CHIP 1.1. Otherwise VF is 1 if any pixel collided.
+    pub collision_counts_rows: bool
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            index_increments: false,
+            wrap_sprites: true,
+            vf_reset: false,
+            display_wait: false,
+            index_overflow_sets_vf: true,
+            collision_counts_rows: false
+        }
+    }
+}
+
+impl Quirks {
+    /// The field names accepted by `set`.
+    pub const NAMES: [&'static str; 8] = [
+        "shift_uses_vy",
+        "jump_uses_vx",
+        "index_increments",
+        "wrap_sprites",
+        "vf_reset",
+        "display_wait",
+        "index_overflow_sets_vf",
+        "collision_counts_rows"
+    ];
+
+    /// Sets the quirk called `name`, e.g. from the command line.
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        let field = match name {
+            "shift_uses_vy" => &mut self.shift_uses_vy,
+            "jump_uses_vx" => &mut self.jump_uses_vx,
+            "index_increments" => &mut self.index_increments,
+            "wrap_sprites" => &mut self.wrap_sprites,
+            "vf_reset" => &mut self.vf_reset,
+            "display_wait" => &mut self.display_wait,
+            "index_overflow_sets_vf" => &mut self.index_overflow_sets_vf,
+            "collision_counts_rows" => &mut self.collision_counts_rows,
+            _ => return Err(format!("unknown quirk '{}', expected one of: {}", name, Quirks::NAMES.join(", ")))
+        };
+        *field = value;
+        Ok(())
+    }
+
+    /// Reads the quirk called `name`, or `None` if there is no such quirk.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "shift_uses_vy" => Some(self.shift_uses_vy),
+            "jump_uses_vx" => Some(self.jump_uses_vx),
+            "index_increments" => Some(self.index_increments),
+            "wrap_sprites" => Some(self.wrap_sprites),
+            "vf_reset" => Some(self.vf_reset),
+            "display_wait" => Some(self.display_wait),
+            "index_overflow_sets_vf" => Some(self.index_overflow_sets_vf),
+            "collision_counts_rows" => Some(self.collision_counts_rows),
+            _ => None
+        }
+    }
+}
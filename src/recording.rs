@@ -0,0 +1,186 @@
+use std::{fs::File, io::BufWriter, path::{Path, PathBuf}};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{chip8::{FRAMES_PER_SECOND, HIRES_HEIGHT, HIRES_WIDTH}, screenshot::{self, Palette}};
+
+// GIF delays are in hundredths of a second. Browsers slow anything shorter than this right down, so faster
+// changes are merged into the frame before
+const MIN_DELAY: u64 = 2;
+
+/// Writes the screen of every emulated frame into an animated GIF. Frames the same as the one before only
+/// make that one last longer, and delays are rounded from the frame count so the clip keeps 60 Hz time.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    path: PathBuf,
+    scale: usize,
+    // The screen waiting to be written, and the frame it first appeared in. It can only be written once the
+    // next change shows how long it lasted
+    pending: Option<(Vec<Vec<u8>>, u64)>,
+    frames: u64
+}
+
+impl GifRecorder {
+    /// Starts a GIF at `path`. The image is the high resolution screen with each pixel `scale` pixels wide,
+    /// and low resolution screens are drawn at twice that.
+    pub fn create(path: impl AsRef<Path>, palette: &Palette, scale: u32) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let scale = scale.max(1) as usize;
+        let (width, height) = (HIRES_WIDTH * scale, HIRES_HEIGHT * scale);
+        let global_palette: Vec<u8> = palette.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let mut encoder = Encoder::new(BufWriter::new(file), width as u16, height as u16, &global_palette)
+            .map_err(|e| e.to_string())?;
+        encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+        Ok(GifRecorder { encoder, path, scale, pending: None, frames: 0 })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the screen at the end of an emulated frame.
+    pub fn record(&mut self, pixels: &[Vec<u8>]) -> Result<(), String> {
+        let frame = self.frames;
+        self.frames += 1;
+        match &mut self.pending {
+            Some((screen, _)) if screen == pixels => {},
+            Some((screen, start)) if centiseconds(frame) - centiseconds(*start) < MIN_DELAY => {
+                *screen = pixels.to_vec();
+            },
+            _ => {
+                if let Some((screen, start)) = self.pending.take() {
+                    self.write(&screen, centiseconds(frame) - centiseconds(start))?;
+                }
+                self.pending = Some((pixels.to_vec(), frame));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the last frame and closes the file.
+    pub fn finish(mut self) -> Result<PathBuf, String> {
+        if let Some((screen, start)) = self.pending.take() {
+            let delay = (centiseconds(self.frames) - centiseconds(start)).max(MIN_DELAY);
+            self.write(&screen, delay)?;
+        }
+        self.encoder.into_inner().map_err(|e| e.to_string())?;
+        Ok(self.path)
+    }
+
+    fn write(&mut self, pixels: &[Vec<u8>], delay: u64) -> Result<(), String> {
+        let scale = self.scale * HIRES_WIDTH / pixels.first().map_or(HIRES_WIDTH, Vec::len).max(1);
+        let data = screenshot::scaled(pixels, scale);
+        let width = pixels.first().map_or(0, Vec::len) * scale;
+        let height = pixels.len() * scale;
+
+        let mut frame = Frame::from_indexed_pixels(width as u16, height as u16, data, None);
+        frame.delay = delay.min(u16::MAX as u64) as u16;
+        self.encoder.write_frame(&frame).map_err(|e| e.to_string())
+    }
+}
+
+/// When `frame` starts, in hundredths of a second, rounded.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    const PALETTE: Palette = [(0, 0, 0), (255, 255, 255), (255, 0, 0), (0, 0, 255)];
+
+    /// A low resolution screen with pixel `n` of the top row on.
+    fn screen(n: usize) -> Vec<Vec<u8>> {
+        let mut pixels = vec![vec![0; SCREEN_WIDTH]; SCREEN_HEIGHT];
+        pixels[0][n] = 1;
+        pixels
+    }
+
+    /// A frame read back from a GIF.
+    struct Decoded {
+        width: u16,
+        delay: u16,
+        pixels: Vec<u8>
+    }
+
+    impl Decoded {
+        /// Where the first pixel on in the first plane is.
+        fn lit(&self) -> usize {
+            self.pixels.iter().position(|pixel| *pixel == 1).unwrap()
+        }
+    }
+
+    /// Records `screens`, one per frame, and reads the GIF back as its (width, height) and frames.
+    fn record(name: &str, scale: u32, screens: &[Vec<Vec<u8>>]) -> ((u16, u16), Vec<Decoded>) {
+        let path = env::temp_dir().join(format!("chip8-{}-{}.gif", name, std::process::id()));
+        let mut recorder = GifRecorder::create(&path, &PALETTE, scale).unwrap();
+        for pixels in screens {
+            recorder.record(pixels).unwrap();
+        }
+        recorder.finish().unwrap();
+        let data = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data.as_slice()).unwrap();
+        let size = (decoder.width(), decoder.height());
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push(Decoded { width: frame.width, delay: frame.delay, pixels: frame.buffer.to_vec() });
+        }
+        (size, frames)
+    }
+
+    fn delays(frames: &[Decoded]) -> Vec<u16> {
+        frames.iter().map(|frame| frame.delay).collect()
+    }
+
+    #[test]
+    fn repeated_screens_make_one_longer_frame() {
+        let (_, frames) = record("repeated", 1, &vec![screen(0); 30]);
+        assert_eq!(delays(&frames), [50]);
+    }
+
+    #[test]
+    fn delays_keep_sixty_frames_a_second() {
+        // A change every 2 frames is 3 1/3 hundredths, rounded to 3 and 4 so they still add up
+        let screens: Vec<_> = (0..60).map(|frame| screen(frame / 2)).collect();
+        let (_, frames) = record("timing", 1, &screens);
+        assert_eq!(frames.len(), 30);
+        assert_eq!(delays(&frames).iter().map(|delay| *delay as u32).sum::<u32>(), 100);
+        assert!(delays(&frames).iter().all(|delay| *delay == 3 || *delay == 4));
+    }
+
+    #[test]
+    fn merges_changes_too_short_to_show() {
+        let (_, frames) = record("merged", 1, &[screen(0), screen(1), screen(2), screen(3)]);
+        // Frames start at 0, 2, 3 and 5 hundredths, so the one at 3 is merged into the one at 2
+        assert_eq!(delays(&frames), [2, 3, 2]);
+        let lit: Vec<usize> = frames.iter().map(Decoded::lit).collect();
+        // Low resolution pixels are 2 wide at scale 1
+        assert_eq!(lit, [0, 4, 6]);
+    }
+
+    #[test]
+    fn keeps_the_width_when_the_resolution_changes() {
+        let mut hires = vec![vec![0; HIRES_WIDTH]; HIRES_HEIGHT];
+        hires[0][1] = 1;
+        let screens = [vec![screen(0); 10], vec![hires; 10], vec![screen(1); 10]].concat();
+        let (size, frames) = record("resolution", 3, &screens);
+        assert_eq!(size, (HIRES_WIDTH as u16 * 3, HIRES_HEIGHT as u16 * 3));
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!((frame.width as usize, frame.pixels.len()), (HIRES_WIDTH * 3, HIRES_WIDTH * HIRES_HEIGHT * 9));
+        }
+        // Pixel 1 is 3 pixels in at high resolution and 6 at low
+        let lit: Vec<usize> = frames.iter().map(Decoded::lit).collect();
+        assert_eq!(lit, [0, 3, 6]);
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+
+use crate::{chip8::Chip8, savestate};
+
+/// A bounded history of machine states to run the emulation backwards through.
+///
+/// Only the newest state is kept whole. Every older one is stored as the bytes that differ from the state after
+/// it, which for a chip8 program is usually a handful of registers and a few bytes of memory.
+pub struct Rewind {
+    // The newest state, encoded like a save state
+    latest: Option<Vec<u8>>,
+    // Oldest first. Applying the newest delta to `latest` gives the state before it, and so on back
+    deltas: VecDeque<Delta>,
+    delta_bytes: usize,
+    max_states: usize,
+    max_bytes: usize,
+    frames_per_state: u32,
+    frames: u32
+}
+
+impl Rewind {
+    /// Keeps at most `seconds` of history taken every `frames_per_state` frames at `frames_per_second`, and
+    /// at most `max_bytes` of it, whichever runs out first.
+    pub fn new(seconds: u32, frames_per_second: u32, frames_per_state: u32, max_bytes: usize) -> Self {
+        let frames_per_state = frames_per_state.max(1);
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            max_states: (seconds as usize * frames_per_second as usize / frames_per_state as usize).max(1),
+            max_bytes,
+            frames_per_state,
+            frames: 0
+        }
+    }
+
+    /// Call once per frame that was run. Every `frames_per_state` frames the state of `chip8` is kept.
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.frames += 1;
+        if self.frames < self.frames_per_state {
+            return;
+        }
+        self.frames = 0;
+
+        let state = savestate::encode(&chip8.snapshot());
+        if let Some(previous) = self.latest.take() {
+            let delta = Delta::between(&state, previous);
+            self.delta_bytes += delta.size();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+        while self.len() > self.max_states || (self.memory_used() > self.max_bytes && !self.deltas.is_empty()) {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.delta_bytes -= oldest.size();
+            }
+        }
+    }
+
+    /// Puts `chip8` back to the newest state it isn't already in, and forgets the ones after it, so calling this
+    /// repeatedly keeps going back. Returns false once there is no older state left.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let Some(mut state) = self.latest.take() else {
+            return false;
+        };
+        // Going back to the state the machine is in would do nothing, so go back one further
+        if state == savestate::encode(&chip8.snapshot()) {
+            match self.deltas.pop_back() {
+                Some(delta) => {
+                    self.delta_bytes -= delta.size();
+                    state = delta.apply(state);
+                },
+                None => {
+                    self.latest = Some(state);
+                    return false;
+                }
+            }
+        }
+        // States come from `savestate::encode`, so they always decode
+        if let Ok(snapshot) = savestate::decode(&state) {
+            let _ = chip8.restore(&snapshot);
+        }
+        // Kept as the newest state, so the next frames recorded are deltas from it
+        self.latest = Some(state);
+        self.frames = 0;
+        true
+    }
+
+    /// Number of states kept.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Roughly how many bytes of history are kept.
+    pub fn memory_used(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames = 0;
+    }
+}
+
+/// How to turn one encoded state back into the one before it.
+struct Delta {
+    /// Length of the older state. It differs from the newer one when the stack depth changed
+    len: usize,
+    /// The bytes that differ, as (offset, bytes) runs
+    changes: Vec<(u32, Vec<u8>)>
+}
+
+impl Delta {
+    /// What to change in `newer` to get `older`.
+    fn between(newer: &[u8], older: Vec<u8>) -> Delta {
+        // Compare as if `newer` were cut or padded with zeroes to the length of `older`
+        let newer_byte = |index: usize| newer.get(index).copied().unwrap_or(0);
+        let mut changes: Vec<(u32, Vec<u8>)> = Vec::new();
+        let mut index = 0;
+        while index < older.len() {
+            if newer_byte(index) == older[index] {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < older.len() && newer_byte(index) != older[index] {
+                index += 1;
+            }
+            changes.push((start as u32, older[start..index].to_vec()));
+        }
+        Delta { len: older.len(), changes }
+    }
+
+    fn apply(self, mut newer: Vec<u8>) -> Vec<u8> {
+        newer.resize(self.len, 0);
+        for (offset, bytes) in self.changes {
+            let offset = offset as usize;
+            newer[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        newer
+    }
+
+    fn size(&self) -> usize {
+        self.changes.iter().map(|(_, bytes)| bytes.len() + 4).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    fn chip8(platform: Platform, program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::from_profile(&platform.profile());
+        chip8.load_bytes(program).unwrap();
+        chip8
+    }
+
+    fn round_trip(newer: &[u8], older: &[u8]) -> Vec<u8> {
+        Delta::between(newer, older.to_vec()).apply(newer.to_vec())
+    }
+
+    #[test]
+    fn deltas_round_trip_bytes_of_any_length() {
+        let cases: [(&[u8], &[u8]); 6] = [
+            (&[1, 2, 3], &[1, 2, 3]),
+            (&[1, 2, 3], &[1, 9, 3, 4, 5]),
+            (&[1, 2, 3, 4, 5], &[0, 2]),
+            (&[1, 2, 3, 4], &[1, 2, 0, 0, 0, 6]),
+            (&[], &[7, 7]),
+            (&[7, 7], &[])
+        ];
+        for (newer, older) in cases {
+            assert_eq!(round_trip(newer, older), older, "{:?} back to {:?}", newer, older);
+        }
+        assert_eq!(Delta::between(&[1, 2, 3], vec![1, 2, 3]).size(), 0);
+    }
+
+    #[test]
+    fn deltas_round_trip_states_across_stack_depth_and_resolution() {
+        // HIGH, CALL 0x206, JP 0x204, then at 0x206: LOW, LD V1, 5, RET
+        let mut chip8 = chip8(Platform::SuperChip, &[0x00, 0xFF, 0x22, 0x06, 0x12, 0x04, 0x00, 0xFE, 0x61, 0x05, 0x00, 0xEE]);
+        let mut states = vec![savestate::encode(&chip8.snapshot())];
+        for _ in 0..6 {
+            chip8.step().unwrap();
+            states.push(savestate::encode(&chip8.snapshot()));
+        }
+        // Hires screens and deeper stacks make for longer states
+        assert!(states.windows(2).any(|pair| pair[0].len() < pair[1].len()));
+        assert!(states.windows(2).any(|pair| pair[0].len() > pair[1].len()));
+
+        for older in &states {
+            for newer in &states {
+                assert_eq!(&round_trip(newer, older), older);
+            }
+        }
+    }
+
+    #[test]
+    fn rewinding_goes_back_one_state_at_a_time() {
+        // ADD V0, 1 over and over
+        let mut chip8 = chip8(Platform::Chip48, &[0x70, 0x01].repeat(20));
+        let mut rewind = Rewind::new(10, 60, 1, usize::MAX);
+        for _ in 0..10 {
+            chip8.step().unwrap();
+            rewind.record(&chip8);
+        }
+        // The newest state is the one the machine is in, so the first step goes back to the one before it
+        for v0 in (1..10).rev() {
+            assert!(rewind.rewind(&mut chip8));
+            assert_eq!(chip8.registers()[0], v0);
+        }
+        assert!(!rewind.rewind(&mut chip8));
+        assert_eq!(chip8.registers()[0], 1);
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn rewinding_between_states_goes_back_to_the_newest() {
+        let mut chip8 = chip8(Platform::Chip48, &[0x70, 0x01].repeat(20));
+        let mut rewind = Rewind::new(10, 60, 4, usize::MAX);
+        for _ in 0..10 {
+            chip8.step().unwrap();
+            rewind.record(&chip8);
+        }
+        // States were kept at 4 and 8, and the machine has run on since
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(chip8.registers()[0], 8);
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(chip8.registers()[0], 4);
+    }
+
+    #[test]
+    fn recording_carries_on_from_where_rewinding_stopped() {
+        let mut chip8 = chip8(Platform::Chip48, &[0x70, 0x01].repeat(20));
+        let mut rewind = Rewind::new(10, 60, 1, usize::MAX);
+        for _ in 0..5 {
+            chip8.step().unwrap();
+            rewind.record(&chip8);
+        }
+        assert!(rewind.rewind(&mut chip8));
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(chip8.registers()[0], 3);
+
+        // The states after the one rewound to are gone, and new ones follow it
+        chip8.step().unwrap();
+        rewind.record(&chip8);
+        assert_eq!(rewind.len(), 4);
+        let mut v0 = Vec::new();
+        while rewind.rewind(&mut chip8) {
+            v0.push(chip8.registers()[0]);
+        }
+        assert_eq!(v0, [3, 2, 1]);
+    }
+
+    #[test]
+    fn the_memory_budget_evicts_the_oldest_states() {
+        let mut chip8 = chip8(Platform::Chip48, &[0x70, 0x01].repeat(60));
+        let state_size = savestate::encode(&chip8.snapshot()).len();
+        let max_bytes = state_size + 100;
+        let mut rewind = Rewind::new(10, 60, 1, max_bytes);
+        for _ in 0..50 {
+            chip8.step().unwrap();
+            rewind.record(&chip8);
+            assert!(rewind.memory_used() <= max_bytes);
+        }
+        let kept = rewind.len();
+        assert!(kept > 1 && kept < 50, "{} states kept", kept);
+
+        // The newest states are the ones left
+        let mut v0 = Vec::new();
+        while rewind.rewind(&mut chip8) {
+            v0.push(chip8.registers()[0]);
+        }
+        assert_eq!(v0, (50 - kept as u8 + 1..50).rev().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn the_state_limit_evicts_the_oldest_states() {
+        let mut chip8 = chip8(Platform::Chip48, &[0x70, 0x01].repeat(100));
+        // One second, a state every 10 frames
+        let mut rewind = Rewind::new(1, 60, 10, usize::MAX);
+        for _ in 0..100 {
+            chip8.step().unwrap();
+            rewind.record(&chip8);
+        }
+        assert_eq!(rewind.len(), 6);
+        assert!(rewind.rewind(&mut chip8));
+        assert_eq!(chip8.registers()[0], 90);
+    }
+}
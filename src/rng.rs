@@ -0,0 +1,193 @@
+use std::{fmt, str::FromStr};
+
+/// The second page of the COSMAC VIP's CHIP-8 interpreter, 0x100 to 0x1FF, which its random routine reads from.
+const VIP_INTERPRETER_PAGE: [u8; 0x100] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x45, 0xA3, 0x98, 0x56, 0xD4, 0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC,
+    0x22, 0xDC, 0x12, 0x56, 0xD4, 0x06, 0xB8, 0xD4, 0x06, 0xA8, 0xD4, 0x64, 0x0A, 0x01, 0xE6, 0x8A,
+    0xF4, 0xAA, 0x3B, 0x28, 0x9A, 0xFC, 0x01, 0xBA, 0xD4, 0xF8, 0x81, 0xBA, 0x06, 0xFA, 0x0F, 0xAA,
+    0x0A, 0xAA, 0xD4, 0xE6, 0x06, 0xBF, 0x93, 0xBE, 0xF8, 0x1B, 0xAE, 0x2A, 0x1A, 0xF8, 0x00, 0x5A,
+    0x0E, 0xF5, 0x3B, 0x4B, 0x56, 0x0A, 0xFC, 0x01, 0x5A, 0x30, 0x40, 0x4E, 0xF6, 0x3B, 0x3C, 0x9F,
+    0x56, 0x2A, 0x2A, 0xD4, 0x00, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x07, 0x5A, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x5B, 0x12, 0xD4, 0x22, 0x86, 0x52, 0xF8, 0xF0, 0xA7, 0x0A, 0x57, 0x87, 0xF3, 0x17,
+    0x1A, 0x3A, 0x6B, 0x12, 0xD4, 0x15, 0x85, 0x22, 0x73, 0x95, 0x52, 0x25, 0x45, 0xA5, 0x86, 0xFA,
+    0x0F, 0xB5, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x82, 0x15, 0x15, 0xD4, 0x45, 0xE6, 0xF3, 0x3A, 0x88,
+    0xD4, 0x45, 0x07, 0x30, 0x8C, 0x45, 0x07, 0x30, 0x84, 0xE6, 0x62, 0x26, 0x45, 0xA3, 0x36, 0x88,
+    0xD4, 0x3E, 0x88, 0xD4, 0xF8, 0xF0, 0xA7, 0xE7, 0x45, 0xF4, 0xA5, 0x86, 0xFA, 0x0F, 0x3B, 0xB2,
+    0xFC, 0x01, 0xB5, 0xD4, 0x45, 0x56, 0xD4, 0x45, 0xE6, 0xF4, 0x56, 0xD4, 0x45, 0xFA, 0x0F, 0x3A,
+    0xC4, 0x07, 0x56, 0xD4, 0xAF, 0x22, 0xF8, 0xD3, 0x73, 0x8F, 0xF9, 0xF0, 0x52, 0xE6, 0x07, 0xD2,
+    0x56, 0xF8, 0xFF, 0xA6, 0xF8, 0x00, 0x7E, 0x56, 0xD4, 0x19, 0x89, 0xAE, 0x93, 0xBE, 0x99, 0xEE,
+    0xF4, 0x56, 0x76, 0xE6, 0xF4, 0xB9, 0x56, 0x45, 0xF2, 0x56, 0xD4, 0x45, 0xAA, 0x86, 0xFA, 0x0F,
+    0xBA, 0xD4, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0, 0x00, 0x4B
+];
+
+/// Which routine CXNN gets its numbers from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngKind {
+    /// SplitMix64. Every byte value is equally likely.
+    Modern,
+    /// The COSMAC VIP interpreter's own routine. Its seed is the 1802's R9 register, which the display
+    /// interrupt counts up once a frame and CXNN counts up again before stirring the high byte with a byte of
+    /// the interpreter's code. It is nowhere near as random as `Modern`.
+    CosmacVip
+}
+
+impl RngKind {
+    pub const ALL: [RngKind; 2] = [RngKind::Modern, RngKind::CosmacVip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngKind::Modern => "modern",
+            RngKind::CosmacVip => "vip"
+        }
+    }
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RngKind::ALL.iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown random number generator '{}', expected modern or vip", s))
+    }
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The random number generator behind CXNN. Its whole state is one number, so it can go in save states
+/// and a run can be repeated exactly by starting from the same seed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    kind: RngKind,
+    state: u64
+}
+
+impl Rng {
+    /// `Rng::new(rng.kind(), rng.state())` carries on exactly where `rng` left off.
+    pub fn new(kind: RngKind, seed: u64) -> Self {
+        Rng { kind, state: seed }
+    }
+
+    /// Seeded from the operating system, so every run is different.
+    pub fn from_entropy(kind: RngKind) -> Self {
+        Self::new(kind, rand::random())
+    }
+
+    pub fn kind(&self) -> RngKind {
+        self.kind
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// A number from 0 to 0xFF.
+    pub fn next_byte(&mut self) -> u8 {
+        match self.kind {
+            RngKind::Modern => (self.next_u64() >> 56) as u8,
+            RngKind::CosmacVip => {
+                // The routine at 0x1D9: INC R9, then add the code byte at 0x100 + R9.0 to R9.1, shift right
+                // through the carry and add the sum back
+                let seed = (self.state as u16).wrapping_add(1);
+                let [low, high] = seed.to_le_bytes();
+                let (sum, carry) = high.overflowing_add(VIP_INTERPRETER_PAGE[low as usize]);
+                let random = (sum >> 1 | u8::from(carry) << 7).wrapping_add(sum);
+                self.state = u64::from(u16::from_le_bytes([low, random]));
+                random
+            }
+        }
+    }
+
+    /// Called at the start of every frame. The VIP's display interrupt counts R9 up, so its numbers depend
+    /// on when CXNN runs as well as how often.
+    pub fn tick(&mut self) {
+        if self.kind == RngKind::CosmacVip {
+            self.state = u64::from((self.state as u16).wrapping_add(1));
+        }
+    }
+
+    // SplitMix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(mut rng: Rng, count: usize) -> Vec<u8> {
+        (0..count).map(|_| rng.next_byte()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        for kind in RngKind::ALL {
+            assert_eq!(bytes(Rng::new(kind, 42), 1000), bytes(Rng::new(kind, 42), 1000), "{}", kind);
+            assert_ne!(bytes(Rng::new(kind, 42), 1000), bytes(Rng::new(kind, 43), 1000), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn carries_on_from_its_state() {
+        for kind in RngKind::ALL {
+            let mut rng = Rng::new(kind, 7);
+            for _ in 0..10 {
+                rng.next_byte();
+                rng.tick();
+            }
+            let copy = Rng::new(rng.kind(), rng.state());
+            assert_eq!(bytes(rng, 100), bytes(copy, 100), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn every_byte_comes_up() {
+        let mut seen = [false; 0x100];
+        for byte in bytes(Rng::new(RngKind::Modern, 0), 0x10000) {
+            seen[byte as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+
+        // One VIP sequence can miss some of them, so which it gives depends on R9
+        let mut seen = [false; 0x100];
+        for seed in 0..0x10000 {
+            seen[Rng::new(RngKind::CosmacVip, seed).next_byte() as usize] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn follows_the_vip_routine() {
+        // From R9 = 0 the first four code bytes read are 0x101 to 0x104, all zero. Then 0x45 at 0x105 gives
+        // 0x45 + 0x22 = 0x67, and 0x67 + 0xA3 at 0x106 carries into (0x0A >> 1 | 0x80) + 0x0A = 0x8F
+        assert_eq!(bytes(Rng::new(RngKind::CosmacVip, 0), 8), [0x00, 0x00, 0x00, 0x00, 0x67, 0x8F, 0xBA, 0x98]);
+
+        // INC R9 carries into the high byte, so 0x12FF reads 0x100 with 0x13 in R9.1
+        let mut rng = Rng::new(RngKind::CosmacVip, 0x12FF);
+        assert_eq!(rng.next_byte(), 0x1C);
+        assert_eq!(rng.state(), 0x1C00);
+
+        // The display interrupt moves R9 on too
+        let mut rng = Rng::new(RngKind::CosmacVip, 3);
+        rng.tick();
+        assert_eq!(rng.next_byte(), 0x67);
+    }
+
+    #[test]
+    fn parses_names() {
+        for kind in RngKind::ALL {
+            assert_eq!(kind.name().to_uppercase().parse::<RngKind>(), Ok(kind));
+        }
+        assert!("xorshift".parse::<RngKind>().is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{chip8::{Chip8, Snapshot, HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH}, error::Chip8Error, platform::Platform, quirks::Quirks, rng::{Rng, RngKind}};
+
+/// The bitplanes, as the bit each sets in a pixel.
+const PLANES: [u8; 2] = [1, 2];
+
+/// Written at the start of every save state.
+const MAGIC: &[u8; 4] = b"CH8S";
+/// Bumped whenever the layout below changes.
+pub const VERSION: u16 = 1;
+
+// Layout, all numbers little endian:
+//   magic, version: u16
+//   platform: u8 (0 none, 1 COSMAC VIP, 2 CHIP-48, 3 SUPER-CHIP, 4 XO-CHIP)
+//   memory length: u32, memory
+//   V0-VF, I: u16, PC: u16
+//   stack depth: u8, stack: u16 each
+//   delay timer: u8, sound timer: u8
+//   width: u16, height: u16 (64x32 or 128x64), then for each of the 2 bitplanes its pixels packed 8 to a byte, row by row,
+//   high bit first
+//   quirks: u16 bitfield in the order of `Quirks::NAMES`, vblank: u8
+//   RNG kind: u8 (0 modern, 1 COSMAC VIP), RNG state: u64
+//   RPL user flags: 16 bytes, selected bitplanes: u8
+//   audio pattern loaded: u8, audio pattern: 16 bytes, pitch: u8
+
+/// Turns `snapshot` into the bytes of a save state file.
+pub fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let mut out = Vec::with_capacity(snapshot.memory.len() + 1024);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.push(match snapshot.platform {
+        None => 0,
+        Some(Platform::CosmacVip) => 1,
+        Some(Platform::Chip48) => 2,
+        Some(Platform::SuperChip) => 3,
+        Some(Platform::XoChip) => 4
+    });
+
+    out.extend_from_slice(&(snapshot.memory.len() as u32).to_le_bytes());
+    out.extend_from_slice(&snapshot.memory);
+    out.extend_from_slice(&snapshot.registers);
+    out.extend_from_slice(&snapshot.register_i.to_le_bytes());
+    out.extend_from_slice(&snapshot.pc.to_le_bytes());
+    out.push(snapshot.stack.len() as u8);
+    for address in &snapshot.stack {
+        out.extend_from_slice(&address.to_le_bytes());
+    }
+    out.push(snapshot.delay_timer);
+    out.push(snapshot.sound_timer);
+
+    let height = snapshot.pixels.len();
+    let width = snapshot.pixels.first().map_or(0, Vec::len);
+    out.extend_from_slice(&(width as u16).to_le_bytes());
+    out.extend_from_slice(&(height as u16).to_le_bytes());
+    for plane in PLANES {
+        let bits: Vec<bool> = snapshot.pixels.iter().flatten().map(|pixel| pixel & plane != 0).collect();
+        for chunk in bits.chunks(8) {
+            out.push(chunk.iter().enumerate().fold(0, |byte, (bit, on)| byte | (*on as u8) << (7 - bit)));
+        }
+    }
+
+    out.extend_from_slice(&quirk_bits(&snapshot.quirks).to_le_bytes());
+    out.push(snapshot.vblank as u8);
+    out.push(match snapshot.rng.kind() {
+        RngKind::Modern => 0,
+        RngKind::CosmacVip => 1
+    });
+    out.extend_from_slice(&snapshot.rng.state().to_le_bytes());
+    out.extend_from_slice(&snapshot.flags);
+    out.push(snapshot.planes);
+    out.push(snapshot.audio_pattern.is_some() as u8);
+    out.extend_from_slice(&snapshot.audio_pattern.unwrap_or_default());
+    out.push(snapshot.pitch);
+    out
+}
+
+/// Reads a save state written by `encode`.
+pub fn decode(data: &[u8]) -> Result<Snapshot, Chip8Error> {
+    let mut reader = Reader { data };
+    if reader.bytes(4)? != MAGIC {
+        return Err(Chip8Error::CorruptSaveState);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(Chip8Error::SaveStateVersion { version });
+    }
+    let platform = match reader.u8()? {
+        0 => None,
+        1 => Some(Platform::CosmacVip),
+        2 => Some(Platform::Chip48),
+        3 => Some(Platform::SuperChip),
+        4 => Some(Platform::XoChip),
+        _ => return Err(Chip8Error::CorruptSaveState)
+    };
+
+    let memory_size = reader.u32()? as usize;
+    let memory = reader.bytes(memory_size)?.to_vec();
+    let registers: [u8; 16] = reader.bytes(16)?.try_into().map_err(|_| Chip8Error::CorruptSaveState)?;
+    let register_i = reader.u16()?;
+    let pc = reader.u16()?;
+    let depth = reader.u8()?;
+    let stack = (0..depth).map(|_| reader.u16()).collect::<Result<Vec<u16>, Chip8Error>>()?;
+    let delay_timer = reader.u8()?;
+    let sound_timer = reader.u8()?;
+
+    let width = reader.u16()? as usize;
+    let height = reader.u16()? as usize;
+    if !matches!((width, height), (SCREEN_WIDTH, SCREEN_HEIGHT) | (HIRES_WIDTH, HIRES_HEIGHT)) {
+        return Err(Chip8Error::CorruptSaveState);
+    }
+    let packed_planes = PLANES.iter()
+        .map(|plane| Ok((*plane, reader.bytes((width * height).div_ceil(8))?)))
+        .collect::<Result<Vec<_>, Chip8Error>>()?;
+    let mut pixels = vec![vec![0; width]; height];
+    for (plane, packed) in packed_planes {
+        for (y, row) in pixels.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let bit = y * width + x;
+                if packed[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    *pixel |= plane;
+                }
+            }
+        }
+    }
+
+    let quirks = quirks_from_bits(reader.u16()?);
+    let vblank = reader.u8()? != 0;
+    let kind = match reader.u8()? {
+        0 => RngKind::Modern,
+        1 => RngKind::CosmacVip,
+        _ => return Err(Chip8Error::CorruptSaveState)
+    };
+    let rng = Rng::new(kind, reader.u64()?);
+    let flags = reader.bytes(16)?.try_into().map_err(|_| Chip8Error::CorruptSaveState)?;
+    let selected_planes = reader.u8()?;
+    let loaded = reader.u8()? != 0;
+    let pattern: [u8; 16] = reader.bytes(16)?.try_into().map_err(|_| Chip8Error::CorruptSaveState)?;
+    let audio_pattern = loaded.then_some(pattern);
+    let pitch = reader.u8()?;
+    if !reader.data.is_empty() {
+        return Err(Chip8Error::CorruptSaveState);
+    }
+
+    Ok(Snapshot { platform, pixels, memory, registers, register_i, pc, stack, delay_timer, sound_timer, quirks, vblank, rng, flags, planes: selected_planes, audio_pattern, pitch })
+}
+
+/// Writes the state of `chip8` to `path`.
+pub fn save(chip8: &Chip8, path: impl AsRef<Path>) -> Result<(), Chip8Error> {
+    fs::write(path, encode(&chip8.snapshot()))?;
+    Ok(())
+}
+
+/// Restores `chip8` from the save state at `path`.
+pub fn load(chip8: &mut Chip8, path: impl AsRef<Path>) -> Result<(), Chip8Error> {
+    let snapshot = decode(&fs::read(path)?)?;
+    chip8.restore(&snapshot)
+}
+
+/// Where numbered slot `slot` for `rom` lives: next to the ROM, e.g. `pong.state1` for `pong.ch8`.
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+fn quirk_bits(quirks: &Quirks) -> u16 {
+    Quirks::NAMES.iter().enumerate()
+        .filter(|(_, name)| quirks.get(name) == Some(true))
+        .fold(0, |bits, (bit, _)| bits | 1 << bit)
+}
+
+fn quirks_from_bits(bits: u16) -> Quirks {
+    let mut quirks = Quirks::default();
+    for (bit, name) in Quirks::NAMES.iter().enumerate() {
+        // Every name in NAMES is accepted by `set`
+        let _ = quirks.set(name, bits & (1 << bit) != 0);
+    }
+    quirks
+}
+
+struct Reader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        if len > self.data.len() {
+            return Err(Chip8Error::CorruptSaveState);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().map_err(|_| Chip8Error::CorruptSaveState)?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().map_err(|_| Chip8Error::CorruptSaveState)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chip8::{MEMORY_SIZE, XO_MEMORY_SIZE}, platform::Platform};
+
+    fn snapshot(platform: Platform, program: &[u8], steps: usize) -> Snapshot {
+        let mut chip8 = Chip8::from_profile(&platform.profile());
+        chip8.load_bytes(program).unwrap();
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+        chip8.snapshot()
+    }
+
+    #[test]
+    fn round_trips_a_low_resolution_screen() {
+        // LD I, 0x200, DRW V0, V0, 4, CALL 0x208, JP 0x208
+        let snapshot = snapshot(Platform::Chip48, &[0xA2, 0x00, 0xD0, 0x04, 0x22, 0x08, 0x00, 0x00, 0x12, 0x08], 4);
+        assert_eq!(snapshot.pixels.len(), SCREEN_HEIGHT);
+        assert_eq!(snapshot.stack.len(), 1);
+        assert_eq!(decode(&encode(&snapshot)).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn round_trips_xo_chip_state() {
+        // HIGH, PLANE 3, LD I, 0x200, AUDIO, DRW V0, V0, 6
+        let snapshot = snapshot(Platform::XoChip, &[0x00, 0xFF, 0xF3, 0x01, 0xA2, 0x00, 0xF0, 0x02, 0xD0, 0x06], 5);
+        assert_eq!(snapshot.pixels.len(), HIRES_HEIGHT);
+        assert_eq!(snapshot.memory.len(), XO_MEMORY_SIZE);
+        assert!(snapshot.audio_pattern.is_some());
+        assert_eq!(decode(&encode(&snapshot)).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn round_trips_the_platform() {
+        let mut chip8 = Chip8::new(Quirks::default());
+        chip8.load_bytes(&[0x12, 0x00]).unwrap();
+        for snapshot in [chip8.snapshot(), snapshot(Platform::CosmacVip, &[0x12, 0x00], 0)] {
+            assert_eq!(decode(&encode(&snapshot)).unwrap().platform, snapshot.platform);
+        }
+        let mut data = encode(&snapshot(Platform::Chip48, &[0x12, 0x00], 0));
+        assert_eq!(data[6], 2);
+        data[6] = 5;
+        assert!(matches!(decode(&data), Err(Chip8Error::CorruptSaveState)));
+    }
+
+    #[test]
+    fn rejects_screens_of_other_sizes() {
+        let mut data = encode(&snapshot(Platform::SuperChip, &[0x12, 0x00], 0));
+        // Magic, version, platform, memory length, memory, V0-VF, I, PC, an empty stack, timers
+        let size = 4 + 2 + 1 + 4 + MEMORY_SIZE + 16 + 2 + 2 + 1 + 2;
+        assert_eq!(data[size..size + 4], [64, 0, 32, 0]);
+        data[size..size + 4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(decode(&data), Err(Chip8Error::CorruptSaveState)));
+        data[size..size + 4].copy_from_slice(&[0, 0, 0, 0]);
+        assert!(matches!(decode(&data), Err(Chip8Error::CorruptSaveState)));
+    }
+
+    #[test]
+    fn restores_only_into_the_same_platform() {
+        let xo_chip = decode(&encode(&snapshot(Platform::XoChip, &[0x12, 0x00], 0))).unwrap();
+        let super_chip = decode(&encode(&snapshot(Platform::SuperChip, &[0x12, 0x00], 0))).unwrap();
+        let mut chip8 = Chip8::from_profile(&Platform::SuperChip.profile());
+        assert!(matches!(chip8.restore(&xo_chip), Err(Chip8Error::CorruptSaveState)));
+        assert!(matches!(Chip8::from_profile(&Platform::XoChip.profile()).restore(&super_chip), Err(Chip8Error::CorruptSaveState)));
+
+        // Same memory size, different instruction set
+        let vip = snapshot(Platform::CosmacVip, &[0x12, 0x00], 0);
+        assert!(matches!(chip8.restore(&vip), Err(Chip8Error::SaveStatePlatform { platform: Some(Platform::CosmacVip) })));
+        assert!(matches!(Chip8::new(Quirks::default()).restore(&vip), Err(Chip8Error::SaveStatePlatform { .. })));
+        assert!(chip8.restore(&super_chip).is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_and_padded_states() {
+        let data = encode(&snapshot(Platform::SuperChip, &[0x12, 0x00], 0));
+        for len in [0, 3, 6, 100, data.len() - 1] {
+            assert!(matches!(decode(&data[..len]), Err(Chip8Error::CorruptSaveState)), "{} bytes", len);
+        }
+        let mut padded = data.clone();
+        padded.push(0);
+        assert!(matches!(decode(&padded), Err(Chip8Error::CorruptSaveState)));
+        let mut future = data;
+        future[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(decode(&future), Err(Chip8Error::SaveStateVersion { .. })));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{fmt, fs, path::{Path, PathBuf}, str::FromStr};
+
+/// Colors for pixels, indexed by the planes they are on in: off, first plane, second plane, both.
+pub type Palette = [(u8, u8, u8); 4];
+
+/// What a screenshot is saved as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// In the colors of the palette, scaled up if asked for
+    Png,
+    /// Raw (P4) portable bitmap, one bit per pixel at the screen's own resolution, 1 for a pixel on in any
+    /// plane. Two of these are equal exactly when the screens were, so they diff well.
+    Pbm
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 2] = [ImageFormat::Png, ImageFormat::Pbm];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Pbm => "pbm"
+        }
+    }
+
+    /// The format a file name's extension asks for.
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        extension.parse()
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ImageFormat::ALL.iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown image format '{}', expected png or pbm", s))
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// `pixels` as palette indices, row by row, each chip8 pixel `scale` pixels wide and high.
+pub fn scaled(pixels: &[Vec<u8>], scale: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(pixels.first().map_or(0, Vec::len) * pixels.len() * scale * scale);
+    for row in pixels {
+        let line: Vec<u8> = row.iter().flat_map(|pixel| std::iter::repeat_n(pixel & 0b11, scale)).collect();
+        for _ in 0..scale {
+            data.extend_from_slice(&line);
+        }
+    }
+    data
+}
+
+/// Encodes `pixels` as a PNG, each chip8 pixel `scale` pixels wide and high.
+pub fn png(pixels: &[Vec<u8>], palette: &Palette, scale: u32) -> Result<Vec<u8>, String> {
+    let scale = scale.max(1) as usize;
+    let width = pixels.first().map_or(0, Vec::len) * scale;
+    let height = pixels.len() * scale;
+    let data = scaled(pixels, scale);
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette.iter().flat_map(|&(r, g, b)| [r, g, b]).collect::<Vec<u8>>());
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&data).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Encodes `pixels` as a raw portable bitmap.
+pub fn pbm(pixels: &[Vec<u8>]) -> Vec<u8> {
+    let width = pixels.first().map_or(0, Vec::len);
+    let mut out = format!("P4\n{} {}\n", width, pixels.len()).into_bytes();
+    for row in pixels {
+        // Rows are padded to whole bytes
+        for chunk in row.chunks(8) {
+            out.push(chunk.iter().enumerate().fold(0, |byte, (bit, pixel)| {
+                if *pixel != 0 { byte | 0x80 >> bit } else { byte }
+            }));
+        }
+    }
+    out
+}
+
+/// Saves `pixels` to `path` in the format its extension names. `palette` and `scale` only matter for PNG.
+pub fn save(pixels: &[Vec<u8>], path: impl AsRef<Path>, palette: &Palette, scale: u32) -> Result<(), String> {
+    let path = path.as_ref();
+    let bytes = match ImageFormat::from_path(path)? {
+        ImageFormat::Png => png(pixels, palette, scale)?,
+        ImageFormat::Pbm => pbm(pixels)
+    };
+    fs::write(path, bytes).map_err(|e| e.to_string())
+}
+
+/// The first file for `rom` with `extension` that doesn't exist yet, next to the ROM, e.g. `pong-1.png` for
+/// `pong.ch8`.
+pub fn next_path(rom: &Path, extension: &str) -> PathBuf {
+    let stem = rom.file_stem().map_or_else(|| "screenshot".into(), |stem| stem.to_string_lossy());
+    (1..).map(|n| rom.with_file_name(format!("{}-{}.{}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: Palette = [(0, 0, 0), (255, 255, 255), (255, 0, 0), (0, 255, 0)];
+
+    fn screen(width: usize, height: usize) -> Vec<Vec<u8>> {
+        vec![vec![0; width]; height]
+    }
+
+    #[test]
+    fn writes_the_pbm_header() {
+        assert!(pbm(&screen(64, 32)).starts_with(b"P4\n64 32\n"));
+        assert!(pbm(&screen(128, 64)).starts_with(b"P4\n128 64\n"));
+    }
+
+    #[test]
+    fn packs_pbm_rows_into_whole_bytes() {
+        let mut pixels = screen(64, 32);
+        pixels[0][0] = 1;
+        pixels[0][9] = 1;
+        pixels[31][63] = 1;
+        let out = pbm(&pixels);
+        let bits = &out[b"P4\n64 32\n".len()..];
+        assert_eq!(bits.len(), 8 * 32);
+        assert_eq!(&bits[..2], &[0x80, 0x40]);
+        assert_eq!(bits[8 * 32 - 1], 0x01);
+
+        let out = pbm(&screen(128, 64));
+        assert_eq!(out.len(), b"P4\n128 64\n".len() + 16 * 64);
+
+        // A row that doesn't fill its last byte is padded with zeros
+        let mut pixels = screen(12, 2);
+        pixels[0][11] = 1;
+        pixels[1][0] = 1;
+        assert_eq!(&pbm(&pixels)[b"P4\n12 2\n".len()..], &[0x00, 0x10, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn pixels_on_in_any_plane_are_set_in_pbm() {
+        let pixels = vec![vec![0, 1, 2, 3, 0, 0, 0, 0]];
+        assert_eq!(&pbm(&pixels)[b"P4\n8 1\n".len()..], &[0b0111_0000]);
+    }
+
+    #[test]
+    fn scales_pixels_to_palette_indices() {
+        let pixels = vec![vec![0, 1], vec![2, 3]];
+        assert_eq!(scaled(&pixels, 2), vec![0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 3, 3, 2, 2, 3, 3]);
+    }
+
+    #[test]
+    fn encodes_scaled_pngs() {
+        let mut pixels = screen(64, 32);
+        pixels[0][1] = 1;
+        pixels[31][63] = 3;
+        for scale in [1, 3] {
+            let out = png(&pixels, &PALETTE, scale).unwrap();
+            let mut reader = png::Decoder::new(std::io::Cursor::new(out)).read_info().unwrap();
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data).unwrap();
+            let scale = scale as usize;
+            assert_eq!((info.width as usize, info.height as usize), (64 * scale, 32 * scale));
+            assert_eq!(info.color_type, png::ColorType::Indexed);
+            assert_eq!(reader.info().palette.as_deref().unwrap(), &[0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 255, 0]);
+
+            let width = 64 * scale;
+            assert_eq!(data[..2 * scale], [vec![0; scale], vec![1; scale]].concat());
+            assert_eq!(data[(scale - 1) * width + scale], 1);
+            assert_eq!(data[data.len() - 1], 3);
+            assert_eq!(data[data.len() - scale - 1], 0);
+            assert_eq!(data.iter().filter(|index| **index != 0).count(), 2 * scale * scale);
+        }
+    }
+}
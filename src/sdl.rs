@@ -0,0 +1,227 @@
+use sdl2::{audio::{AudioCallback, AudioDevice, AudioSpecDesired}, event::Event, keyboard::{Mod, Scancode}, pixels::Color, rect::Rect, render::Canvas, video::Window, EventPump};
+
+use crate::{audio::{PatternPlayer, Sound}, config::{DARK_COLOR, LIGHT_COLOR, PLANE_2_COLOR, PLANE_3_COLOR}, frontend::{Frontend, Input, SlotRequest}, platform::Profile, screenshot::ImageFormat};
+
+
+const WINDOW_WIDTH: u32 = 1024;
+
+/// How the window looks. The defaults come from config.rs.
+#[derive(Clone, Debug)]
+pub struct SdlOptions {
+    /// Window pixels per chip8 pixel at the largest resolution. `None` makes the window 1024 pixels wide.
+    pub scale: Option<u32>,
+    /// Color of pixels that are on
+    pub foreground: (u8, u8, u8),
+    /// Color of pixels that are off
+    pub background: (u8, u8, u8),
+    /// Color of pixels only on in XO-CHIP's second bitplane
+    pub plane_2: (u8, u8, u8),
+    /// Color of pixels on in both XO-CHIP bitplanes
+    pub plane_3: (u8, u8, u8)
+}
+
+impl Default for SdlOptions {
+    fn default() -> Self {
+        SdlOptions {
+            scale: None,
+            foreground: DARK_COLOR,
+            background: LIGHT_COLOR,
+            plane_2: PLANE_2_COLOR,
+            plane_3: PLANE_3_COLOR
+        }
+    }
+}
+
+/// Window, speaker and keyboard for a `Chip8` core.
+pub struct SdlFrontend {
+    canvas: Canvas<Window>,
+    audio_device: AudioDevice<Speaker>,
+    // What the speaker was last told to play, so the audio thread is only locked when it changes
+    sound: Option<Sound>,
+    options: SdlOptions,
+    events: EventPump
+}
+
+impl SdlFrontend {
+
+    /// Opens a window big enough for the largest resolution of `profile`.
+    pub fn new(profile: &Profile, options: SdlOptions) -> Result<Self, String> {
+
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+        let audio_subsystem = sdl_context.audio()?;
+        let event_pump = sdl_context.event_pump()?;
+
+        let (screen_width, screen_height) = profile.screen_size;
+        let scale = options.scale.unwrap_or(WINDOW_WIDTH / screen_width as u32);
+        let window = video_subsystem.window("Chip-8 Emulator", screen_width as u32 * scale, screen_height as u32 * scale)
+            .position_centered()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        canvas.set_draw_color(rgb(options.foreground));
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None
+        };
+
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            Speaker {
+                player: PatternPlayer::new(Sound::beep()),
+                rate: spec.freq as u32,
+                volume: 0.05
+            }
+        })?;
+
+        Ok(SdlFrontend {
+            canvas,
+            audio_device: device,
+            sound: None,
+            options,
+            events: event_pump
+        })
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn present(&mut self, pixels: &[Vec<u8>]) {
+
+        // Indexed by the planes a pixel is on in
+        let colors = [self.options.background, self.options.foreground, self.options.plane_2, self.options.plane_3].map(rgb);
+        let black = colors[0];
+
+        // Scale whatever resolution the program is using to fill the window. When the window isn't a multiple
+        // of it, the leftover edge is cleared rather than showing the last frame at the other resolution
+        let size = self.canvas.window().size();
+        let pixel_width = size.0 / pixels[0].len() as u32;
+        self.canvas.set_draw_color(black);
+        self.canvas.clear();
+
+        for (y_index ,row) in pixels.iter().enumerate() {
+            for (x_index, pixel) in row.iter().enumerate() {
+                let y = (y_index * pixel_width as usize) as i32;
+                let x = (x_index * pixel_width as usize) as i32;
+                let rect = Rect::new(x, y, pixel_width, pixel_width);
+
+                self.canvas.set_draw_color(colors[(*pixel & 0b11) as usize]);
+
+                let _ = self.canvas.fill_rect(rect);
+            }
+        }
+
+        self.canvas.present();
+
+    }
+
+    fn play_sound(&mut self, sound: Option<Sound>) {
+        let Some(sound) = sound else {
+            self.audio_device.pause();
+            return;
+        };
+        if self.sound != Some(sound) {
+            // Locking pauses the audio callback, so no buffer is filled with half of each sound
+            self.audio_device.lock().player.set_sound(sound);
+            self.sound = Some(sound);
+        }
+        self.audio_device.resume();
+    }
+
+    fn poll_input(&mut self) -> Input {
+        let mut input = Input::default();
+        let keys_pressed = &mut input.keys;
+
+        for scancode in self.events.keyboard_state().pressed_scancodes() {
+            match scancode {
+                Scancode::Num1 => { keys_pressed[0x1] = true; },
+                Scancode::Num2 => { keys_pressed[0x2] = true; },
+                Scancode::Num3 => { keys_pressed[0x3] = true; },
+                Scancode::Num4 => { keys_pressed[0xC] = true; },
+                Scancode::Q => { keys_pressed[0x4] = true; },
+                Scancode::W => { keys_pressed[0x5] = true; },
+                Scancode::E => { keys_pressed[0x6] = true; },
+                Scancode::R => { keys_pressed[0xD] = true; },
+                Scancode::A => { keys_pressed[0x7] = true; },
+                Scancode::S => { keys_pressed[0x8] = true; },
+                Scancode::D => { keys_pressed[0x9] = true; },
+                Scancode::F => { keys_pressed[0xE] = true; },
+                Scancode::Z => { keys_pressed[0xA] = true; },
+                Scancode::X => { keys_pressed[0x0] = true; },
+                Scancode::C => { keys_pressed[0xB] = true; },
+                Scancode::V => { keys_pressed[0xF] = true; },
+                Scancode::Backspace => { input.rewinding = true; },
+                _ => {}
+            }
+        }
+
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. } => { input.quit = true; },
+                Event::KeyDown { scancode: Some(scancode), keymod, repeat: false, .. } => {
+                    if let Some(slot) = slot_key(scancode) {
+                        let request = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                            SlotRequest::Save(slot)
+                        } else {
+                            SlotRequest::Load(slot)
+                        };
+                        input.slot_requests.push(request);
+                    }
+                    if scancode == Scancode::F10 {
+                        input.toggle_gif = true;
+                    }
+                    if scancode == Scancode::F12 {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        input.screenshots.push(if shift { ImageFormat::Pbm } else { ImageFormat::Png });
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        input
+    }
+
+    fn show_message(&mut self, message: &str) {
+        let _ = self.canvas.window_mut().set_title(&format!("Chip-8 Emulator - {}", message));
+    }
+}
+
+/// F1-F9 load save state slots 1-9, and with shift held they save to them.
+fn slot_key(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::F1 => Some(1),
+        Scancode::F2 => Some(2),
+        Scancode::F3 => Some(3),
+        Scancode::F4 => Some(4),
+        Scancode::F5 => Some(5),
+        Scancode::F6 => Some(6),
+        Scancode::F7 => Some(7),
+        Scancode::F8 => Some(8),
+        Scancode::F9 => Some(9),
+        _ => None
+    }
+}
+
+fn rgb((r, g, b): (u8, u8, u8)) -> Color {
+    Color::RGB(r, g, b)
+}
+
+
+/// Runs on SDL's audio thread. The frontend swaps the sound through `AudioDevice::lock`.
+struct Speaker {
+    player: PatternPlayer,
+    // Samples per second the device asked for
+    rate: u32,
+    volume: f32
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.player.fill(out, self.rate, self.volume);
+    }
+}
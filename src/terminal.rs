@@ -0,0 +1,87 @@
+use std::{fmt, str::FromStr};
+
+/// How pixels are packed into characters when the screen is drawn in a terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    /// `▀` with the top pixel as the foreground color and the bottom one as the background, so one character
+    /// is 1x2 pixels and every XO-CHIP plane keeps its color.
+    HalfBlock,
+    /// Braille patterns, one dot per pixel, so one character is 2x4 pixels. A character has one foreground
+    /// color, so the dots take the color of the highest plane lit among them.
+    Braille
+}
+
+impl Glyphs {
+    pub const ALL: [Glyphs; 2] = [Glyphs::HalfBlock, Glyphs::Braille];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Glyphs::HalfBlock => "half",
+            Glyphs::Braille => "braille"
+        }
+    }
+
+    /// Width and height in pixels of one character.
+    pub fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4)
+        }
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Glyphs::ALL.iter()
+            .find(|glyphs| glyphs.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| format!("unknown glyphs '{}', expected half or braille", s))
+    }
+}
+
+impl fmt::Display for Glyphs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One character of the screen. The colors are plane bitmasks like the pixels, to be looked up in a palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    pub foreground: u8,
+    pub background: u8
+}
+
+// Braille dot bits, indexed by [y][x] within the character
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Packs `pixels` into rows of characters. Pixels past the bottom or right edge count as off.
+pub fn cells(pixels: &[Vec<u8>], glyphs: Glyphs) -> Vec<Vec<Cell>> {
+    let (cell_width, cell_height) = glyphs.cell_size();
+    let pixel = |x: usize, y: usize| pixels.get(y).and_then(|row| row.get(x)).map_or(0, |pixel| pixel & 0b11);
+    let width = pixels.first().map_or(0, Vec::len);
+
+    (0..pixels.len()).step_by(cell_height).map(|top| {
+        (0..width).step_by(cell_width).map(|left| match glyphs {
+            Glyphs::HalfBlock => Cell { glyph: '▀', foreground: pixel(left, top), background: pixel(left, top + 1) },
+            Glyphs::Braille => {
+                let mut dots = 0;
+                let mut foreground = 0;
+                for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                    for (dx, bit) in row.iter().enumerate() {
+                        let planes = pixel(left + dx, top + dy);
+                        if planes != 0 {
+                            dots |= bit;
+                            foreground = foreground.max(planes);
+                        }
+                    }
+                }
+                let glyph = char::from_u32(0x2800 + dots).unwrap_or(' ');
+                Cell { glyph, foreground: foreground.max(1), background: 0 }
+            }
+        }).collect()
+    }).collect()
+}
@@ -0,0 +1,419 @@
+use std::{fmt, io, str::FromStr};
+
+use crate::{chip8::Chip8, instruction::{decode, Instruction}};
+
+/// How a `Tracer` writes its records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction, meant for reading and diffing
+    Text,
+    /// One JSON object per line
+    Json
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(format!("unknown trace format '{}', expected text or json", s))
+        }
+    }
+}
+
+/// A rough grouping of instructions by what they do, for filtering traces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpcodeClass {
+    /// Jumps, calls, returns and skips
+    Flow,
+    /// Arithmetic and logic on the V registers
+    Alu,
+    /// Instructions that set the index register
+    Index,
+    /// Loads and stores between registers and memory
+    Memory,
+    Display,
+    Input,
+    Timer,
+    Sound,
+    Unknown
+}
+
+impl OpcodeClass {
+    pub const ALL: [OpcodeClass; 9] = [
+        OpcodeClass::Flow,
+        OpcodeClass::Alu,
+        OpcodeClass::Index,
+        OpcodeClass::Memory,
+        OpcodeClass::Display,
+        OpcodeClass::Input,
+        OpcodeClass::Timer,
+        OpcodeClass::Sound,
+        OpcodeClass::Unknown
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OpcodeClass::Flow => "flow",
+            OpcodeClass::Alu => "alu",
+            OpcodeClass::Index => "index",
+            OpcodeClass::Memory => "memory",
+            OpcodeClass::Display => "display",
+            OpcodeClass::Input => "input",
+            OpcodeClass::Timer => "timer",
+            OpcodeClass::Sound => "sound",
+            OpcodeClass::Unknown => "unknown"
+        }
+    }
+
+    pub fn of(instruction: Instruction) -> OpcodeClass {
+        match instruction {
+            Instruction::Sys { .. } | Instruction::Return | Instruction::Jump { .. } | Instruction::Call { .. }
+            | Instruction::SkipEqImm { .. } | Instruction::SkipNeImm { .. } | Instruction::SkipEqReg { .. }
+            | Instruction::SkipNeReg { .. } | Instruction::JumpOffset { .. } | Instruction::Exit => OpcodeClass::Flow,
+            Instruction::LoadImm { .. } | Instruction::AddImm { .. } | Instruction::Move { .. } | Instruction::Or { .. }
+            | Instruction::And { .. } | Instruction::Xor { .. } | Instruction::AddReg { .. } | Instruction::Sub { .. }
+            | Instruction::ShiftRight { .. } | Instruction::SubReverse { .. } | Instruction::ShiftLeft { .. }
+            | Instruction::Random { .. } => OpcodeClass::Alu,
+            Instruction::LoadIndex { .. } | Instruction::AddIndex { .. } | Instruction::LoadFont { .. }
+            | Instruction::LoadBigFont { .. } | Instruction::LoadLongIndex => OpcodeClass::Index,
+            Instruction::Bcd { .. } | Instruction::Store { .. } | Instruction::Load { .. } | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } | Instruction::SaveRange { .. } | Instruction::LoadRange { .. } => OpcodeClass::Memory,
+            Instruction::Clear | Instruction::Draw { .. } | Instruction::ScrollDown { .. } | Instruction::ScrollRight
+            | Instruction::ScrollLeft | Instruction::LowRes | Instruction::HighRes | Instruction::ScrollUp { .. }
+            | Instruction::SelectPlanes { .. } => OpcodeClass::Display,
+            Instruction::SkipKey { .. } | Instruction::SkipNotKey { .. } | Instruction::WaitKey { .. } => OpcodeClass::Input,
+            Instruction::LoadDelay { .. } | Instruction::SetDelay { .. } => OpcodeClass::Timer,
+            Instruction::SetSound { .. } | Instruction::LoadAudio | Instruction::SetPitch { .. } => OpcodeClass::Sound,
+            Instruction::Unknown(_) => OpcodeClass::Unknown
+        }
+    }
+}
+
+impl FromStr for OpcodeClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OpcodeClass::ALL.iter()
+            .find(|class| class.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<&str> = OpcodeClass::ALL.iter().map(|class| class.name()).collect();
+                format!("unknown opcode class '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+impl fmt::Display for OpcodeClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The state of the machine just before it executes one instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub registers: [u8; 16],
+    pub register_i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack_depth: usize
+}
+
+impl TraceRecord {
+    pub fn new(chip8: &Chip8, opcode: u16) -> Self {
+        TraceRecord {
+            pc: chip8.pc(),
+            opcode,
+            instruction: decode(opcode),
+            registers: *chip8.registers(),
+            register_i: chip8.register_i(),
+            delay_timer: chip8.delay_timer(),
+            sound_timer: chip8.sound_timer(),
+            stack_depth: chip8.stack().len()
+        }
+    }
+
+    /// Writes the record as one line in `format`.
+    pub fn write(&self, out: &mut dyn io::Write, format: TraceFormat) -> io::Result<()> {
+        match format {
+            TraceFormat::Text => writeln!(out, "{}", self),
+            TraceFormat::Json => {
+                let registers: Vec<String> = self.registers.iter().map(|value| value.to_string()).collect();
+                // Mnemonics never contain quotes or backslashes, so the text needs no escaping
+                writeln!(out, "{{\"pc\":{},\"opcode\":{},\"instruction\":\"{}\",\"v\":[{}],\"i\":{},\"dt\":{},\"st\":{},\"sp\":{}}}",
+                    self.pc, self.opcode, self.instruction, registers.join(","), self.register_i, self.delay_timer,
+                    self.sound_timer, self.stack_depth)
+            }
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:03X} {:04X} {:<16}", self.pc, self.opcode, self.instruction.to_string())?;
+        for (index, value) in self.registers.iter().enumerate() {
+            write!(f, " V{:X}={:02X}", index, value)?;
+        }
+        write!(f, " I={:03X} DT={:02X} ST={:02X} SP={}", self.register_i, self.delay_timer, self.sound_timer, self.stack_depth)
+    }
+}
+
+impl FromStr for TraceRecord {
+    type Err = String;
+
+    /// Reads a record back from either format. The mnemonic is ignored and decoded from the opcode again.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with('{') {
+            parse_json(s)
+        } else {
+            parse_text(s)
+        }
+    }
+}
+
+fn parse_text(line: &str) -> Result<TraceRecord, String> {
+    let mut words = line.split_whitespace();
+    let mut hex = |name: &str| words.next()
+        .and_then(|word| u16::from_str_radix(word, 16).ok())
+        .ok_or_else(|| format!("expected the {} in hex at the start of the line", name));
+    let pc = hex("address")?;
+    let opcode = hex("opcode")?;
+
+    let mut registers = [None; 16];
+    let (mut register_i, mut delay_timer, mut sound_timer, mut stack_depth) = (None, None, None, None);
+    for (key, value) in words.filter_map(|word| word.split_once('=')) {
+        let bad_value = || format!("{} has a bad value '{}'", key, value);
+        let number = |radix| u16::from_str_radix(value, radix).map_err(|_| bad_value());
+        let byte = || u8::from_str_radix(value, 16).map_err(|_| bad_value());
+        match key {
+            "I" => register_i = Some(number(16)?),
+            "DT" => delay_timer = Some(byte()?),
+            "ST" => sound_timer = Some(byte()?),
+            "SP" => stack_depth = Some(number(10)? as usize),
+            _ => {
+                let index = key.strip_prefix('V').filter(|digit| digit.len() == 1)
+                    .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                    .ok_or_else(|| format!("unknown field '{}'", key))?;
+                registers[index] = Some(byte()?);
+            }
+        }
+    }
+    record(pc, opcode, registers, register_i, delay_timer, sound_timer, stack_depth)
+}
+
+/// Reads the flat objects `TraceRecord::write` produces. Not a general JSON parser.
+fn parse_json(line: &str) -> Result<TraceRecord, String> {
+    let body = line.strip_prefix('{').and_then(|rest| rest.strip_suffix('}')).ok_or("expected a JSON object")?;
+    let (mut pc, mut opcode) = (None, None);
+    let mut registers = [None; 16];
+    let (mut register_i, mut delay_timer, mut sound_timer, mut stack_depth) = (None, None, None, None);
+
+    let mut rest = body.trim();
+    while !rest.is_empty() {
+        let (key, after_key) = rest.strip_prefix('"').and_then(|rest| rest.split_once('"')).ok_or("expected a quoted key")?;
+        let after_colon = after_key.trim_start().strip_prefix(':').ok_or("expected ':' after a key")?.trim_start();
+        // A value runs up to the next comma outside of brackets and quotes
+        let end = match after_colon.chars().next() {
+            Some('"') => after_colon[1..].find('"').map(|end| end + 2),
+            Some('[') => after_colon.find(']').map(|end| end + 1),
+            _ => Some(after_colon.find(',').unwrap_or(after_colon.len()))
+        }.ok_or_else(|| format!("unterminated value for '{}'", key))?;
+        let value = after_colon[..end].trim();
+        rest = after_colon[end..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+
+        let number = |text: &str| text.trim().parse::<u16>().map_err(|_| format!("{} has a bad value '{}'", key, text));
+        let byte = |text: &str| text.trim().parse::<u8>().map_err(|_| format!("{} has a bad value '{}'", key, text));
+        match key {
+            "pc" => pc = Some(number(value)?),
+            "opcode" => opcode = Some(number(value)?),
+            "i" => register_i = Some(number(value)?),
+            "dt" => delay_timer = Some(byte(value)?),
+            "st" => sound_timer = Some(byte(value)?),
+            "sp" => stack_depth = Some(number(value)? as usize),
+            "v" => {
+                let values = value.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')).ok_or("v must be an array")?;
+                let values: Vec<&str> = values.split(',').collect();
+                if values.len() != 16 {
+                    return Err(format!("v must have 16 values, got {}", values.len()));
+                }
+                for (register, value) in registers.iter_mut().zip(values) {
+                    *register = Some(byte(value)?);
+                }
+            },
+            _ => {}
+        }
+    }
+    record(pc.ok_or("missing pc")?, opcode.ok_or("missing opcode")?, registers, register_i, delay_timer, sound_timer, stack_depth)
+}
+
+fn record(pc: u16, opcode: u16, registers: [Option<u8>; 16], register_i: Option<u16>, delay_timer: Option<u8>,
+          sound_timer: Option<u8>, stack_depth: Option<usize>) -> Result<TraceRecord, String> {
+    let mut values = [0; 16];
+    for (index, (value, register)) in values.iter_mut().zip(registers).enumerate() {
+        *value = register.ok_or_else(|| format!("missing V{:X}", index))?;
+    }
+    Ok(TraceRecord {
+        pc,
+        opcode,
+        instruction: decode(opcode),
+        registers: values,
+        register_i: register_i.ok_or("missing I")?,
+        delay_timer: delay_timer.ok_or("missing DT")?,
+        sound_timer: sound_timer.ok_or("missing ST")?,
+        stack_depth: stack_depth.ok_or("missing SP")?
+    })
+}
+
+/// Which instructions make it into a trace. An empty filter lets everything through.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only trace instructions at addresses from `.0` to `.1`, inclusive
+    pub range: Option<(u16, u16)>,
+    /// Only trace instructions in one of these classes
+    pub classes: Vec<OpcodeClass>
+}
+
+impl TraceFilter {
+    pub fn matches(&self, record: &TraceRecord) -> bool {
+        self.range.is_none_or(|(start, end)| (start..=end).contains(&record.pc))
+            && (self.classes.is_empty() || self.classes.contains(&OpcodeClass::of(record.instruction)))
+    }
+}
+
+/// Writes a `TraceRecord` for every instruction a `Chip8` executes. Hand one to `Chip8::set_tracer`.
+pub struct Tracer {
+    out: Box<dyn io::Write>,
+    format: TraceFormat,
+    filter: TraceFilter
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn io::Write>, format: TraceFormat, filter: TraceFilter) -> Self {
+        Tracer { out, format, filter }
+    }
+
+    pub fn trace(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if !self.filter.matches(record) {
+            return Ok(());
+        }
+        record.write(&mut self.out, self.format)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+
+    /// A record from a machine with every register different, in the middle of a subroutine.
+    fn record() -> TraceRecord {
+        // LD V0..VF, 0x10..0x1F, then LD I, 0x345, LD DT, V1, LD ST, V2, CALL 0x230
+        let mut program: Vec<u8> = (0..16).flat_map(|x| [0x60 | x, 0x10 + x]).collect();
+        program.extend_from_slice(&[0xA3, 0x45, 0xF1, 0x15, 0xF2, 0x18, 0x22, 0x30]);
+        let mut chip8 = Chip8::from_profile(&Platform::SuperChip.profile());
+        chip8.load_bytes(&program).unwrap();
+        for _ in 0..20 {
+            chip8.step().unwrap();
+        }
+        TraceRecord::new(&chip8, chip8.fetch().unwrap())
+    }
+
+    fn json(record: &TraceRecord) -> String {
+        let mut out = Vec::new();
+        record.write(&mut out, TraceFormat::Json).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn error(line: &str) -> String {
+        line.parse::<TraceRecord>().unwrap_err()
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let record = record();
+        assert_eq!((record.pc, record.register_i, record.delay_timer, record.sound_timer, record.stack_depth), (0x230, 0x345, 0x11, 0x12, 1));
+        let text = record.to_string();
+        assert!(text.starts_with("230 0000 SYS 0x000"), "{}", text);
+        assert_eq!(text.parse::<TraceRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let record = record();
+        assert_eq!(json(&record).parse::<TraceRecord>(), Ok(record));
+    }
+
+    #[test]
+    fn json_keys_can_come_in_any_order_with_extra_ones() {
+        let line = r#" { "sp" : 1, "extra": "a, [b]", "v": [16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31],
+            "st":18,"dt":17,"i":837,"instruction":"SYS 0x000","opcode":0,"pc":560 } "#;
+        assert_eq!(line.replace('\n', " ").parse::<TraceRecord>(), Ok(record()));
+    }
+
+    #[test]
+    fn the_mnemonic_is_decoded_again() {
+        let text = record().to_string().replace("SYS 0x000", "NONSENSE");
+        assert_eq!(text.parse::<TraceRecord>().unwrap().instruction, Instruction::Sys { nnn: 0 });
+    }
+
+    #[test]
+    fn rejects_malformed_text() {
+        let text = record().to_string();
+        let cases = [
+            (String::new(), "address"),
+            (String::from("2G0 1234"), "address"),
+            (String::from("200"), "opcode"),
+            (text.replace(" V3=13", ""), "missing V3"),
+            (text.replace(" SP=1", ""), "missing SP"),
+            (text.replace("V3=13", "V3=100"), "bad value"),
+            (text.replace("V3=13", "V3=-1"), "bad value"),
+            (text.replace("DT=11", "DT=1FF"), "bad value"),
+            (text.replace("I=345", "I=10000"), "bad value"),
+            (text.replace("V3=13", "VG=13"), "unknown field"),
+            (text.replace("V3=13", "V10=13"), "unknown field"),
+            (format!("{} X=1", text), "unknown field")
+        ];
+        for (line, message) in cases {
+            let error = error(&line);
+            assert!(error.contains(message), "'{}' gave '{}', expected '{}'", line, error, message);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        let json = json(&record());
+        let cases = [
+            (String::from("{"), "JSON object"),
+            (String::from("{pc: 1}"), "quoted key"),
+            (String::from("{\"pc\" 1}"), "':'"),
+            (String::from("{\"pc\": \"1}"), "unterminated"),
+            (String::from("{\"v\": [1, 2}"), "unterminated"),
+            (json.replace("\"pc\":560,", ""), "missing pc"),
+            (json.replace("\"opcode\":0,", ""), "missing opcode"),
+            (json.replace("\"sp\":1", "\"spare\":1"), "missing SP"),
+            (json.replace("[16,17,", "[17,"), "16 values"),
+            (json.replace("[16,", "[256,"), "bad value"),
+            (json.replace("[16,", "[,"), "bad value"),
+            (json.replace("\"dt\":17", "\"dt\":300"), "bad value"),
+            (json.replace("\"pc\":560", "\"pc\":-1"), "bad value"),
+            (json.replace("\"pc\":560", "\"pc\":65536"), "bad value"),
+            (json.replace("\"v\":[", "\"v\":\"").replace("],", "\","), "array")
+        ];
+        for (line, message) in cases {
+            let error = error(&line);
+            assert!(error.contains(message), "'{}' gave '{}', expected '{}'", line, error, message);
+        }
+    }
+}
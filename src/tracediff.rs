@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use crate::{chip8::Chip8, clock::InstructionBudget, error::Chip8Error, trace::TraceRecord};
+
+/// Key presses and releases at fixed frames, so a run can be repeated exactly.
+///
+/// Each line is a frame number followed by `+K` to press or `-K` to release hex key `K`, e.g. `120 +5 -A`.
+/// Blank lines and anything after `#` are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    // (frame, key, pressed), sorted by frame
+    events: Vec<(u64, u8, bool)>
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let Some(frame) = words.next() else { continue };
+            let frame: u64 = frame.parse().map_err(|_| format!("line {}: expected a frame number, got '{}'", number + 1, frame))?;
+            for word in words {
+                let (pressed, key) = match word.split_at_checked(1) {
+                    Some(("+", key)) => (true, key),
+                    Some(("-", key)) => (false, key),
+                    _ => return Err(format!("line {}: expected +KEY or -KEY, got '{}'", number + 1, word))
+                };
+                let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16)
+                    .ok_or_else(|| format!("line {}: expected a hex key from 0 to F, got '{}'", number + 1, key))?;
+                events.push((frame, key, pressed));
+            }
+        }
+        // Stable, so events on the same frame keep their order
+        events.sort_by_key(|(frame, _, _)| *frame);
+        Ok(InputScript { events })
+    }
+
+    /// Presses and releases the keys scripted for `frame`.
+    pub fn apply(&self, chip8: &mut Chip8, frame: u64) {
+        let start = self.events.partition_point(|(event_frame, _, _)| *event_frame < frame);
+        for (_, key, pressed) in self.events[start..].iter().take_while(|(event_frame, _, _)| *event_frame == frame) {
+            chip8.set_key(*key, *pressed);
+        }
+    }
+}
+
+/// Where our execution first differed from the reference.
+#[derive(Debug)]
+pub struct Divergence {
+    /// Number of instructions that matched before this one
+    pub step: u64,
+    pub frame: u64,
+    pub expected: TraceRecord,
+    pub actual: TraceRecord,
+    /// The last record both traces agreed on
+    pub previous: Option<TraceRecord>
+}
+
+impl Divergence {
+    /// The names of the fields that differ, e.g. `PC, V3, I`.
+    pub fn fields(&self) -> Vec<String> {
+        let (expected, actual) = (&self.expected, &self.actual);
+        let mut fields = Vec::new();
+        if expected.pc != actual.pc { fields.push(String::from("PC")); }
+        if expected.opcode != actual.opcode { fields.push(String::from("opcode")); }
+        for (index, (a, b)) in expected.registers.iter().zip(&actual.registers).enumerate() {
+            if a != b { fields.push(format!("V{:X}", index)); }
+        }
+        if expected.register_i != actual.register_i { fields.push(String::from("I")); }
+        if expected.delay_timer != actual.delay_timer { fields.push(String::from("DT")); }
+        if expected.sound_timer != actual.sound_timer { fields.push(String::from("ST")); }
+        if expected.stack_depth != actual.stack_depth { fields.push(String::from("SP")); }
+        fields
+    }
+
+    /// A few lines describing the divergence for a person to read.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Traces diverge after {} matching instructions, in frame {}", self.step, self.frame);
+        let _ = writeln!(out, "Differs in: {}", self.fields().join(", "));
+        if let Some(previous) = &self.previous {
+            let _ = writeln!(out, "previous: {}", previous);
+        }
+        let _ = writeln!(out, "expected: {}", self.expected);
+        let _ = write!(out, "actual:   {}", self.actual);
+        out
+    }
+}
+
+/// How a comparison ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// Every record in the reference matched
+    Matched { steps: u64 },
+    Diverged(Box<Divergence>),
+    /// The program faulted while the reference still had records left
+    Fault { step: u64, error: Chip8Error }
+}
+
+/// Runs `chip8` one instruction per reference record, starting each frame by ticking the timers and
+/// applying `input`, until the reference runs out or the two disagree.
+pub fn compare(chip8: &mut Chip8, reference: impl IntoIterator<Item = Result<TraceRecord, String>>,
+               input: &InputScript, mut instructions: InstructionBudget) -> Result<Outcome, String> {
+    let mut previous = None;
+    let mut step = 0;
+    let mut frame = 0;
+    // The step the next frame starts at
+    let mut frame_start = 0;
+    for expected in reference {
+        let expected = expected?;
+        if step == frame_start {
+            chip8.tick_timers();
+            input.apply(chip8, frame);
+            frame += 1;
+            frame_start += instructions.next_frame().max(1) as u64;
+        }
+
+        let actual = match chip8.fetch() {
+            Ok(opcode) => TraceRecord::new(chip8, opcode),
+            Err(error) => return Ok(Outcome::Fault { step, error })
+        };
+        if actual != expected {
+            return Ok(Outcome::Diverged(Box::new(Divergence { step, frame: frame - 1, expected, actual, previous })));
+        }
+        if let Err(error) = chip8.step() {
+            return Ok(Outcome::Fault { step, error });
+        }
+        previous = Some(actual);
+        step += 1;
+    }
+    Ok(Outcome::Matched { steps: step })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chip8::FRAMES_PER_SECOND, platform::Platform};
+
+    // LD V0, 5, SKP V0, LD V1, 1, ADD V2, 1, JP 0x206
+    const PROGRAM: [u8; 10] = [0x60, 0x05, 0xE0, 0x9E, 0x61, 0x01, 0x72, 0x01, 0x12, 0x06];
+
+    fn chip8(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::from_profile(&Platform::Chip48.profile());
+        chip8.load_bytes(program).unwrap();
+        chip8
+    }
+
+    /// The first `steps` records of `program`, run with `keys` held. Nothing in it uses the timers, so frames
+    /// don't matter.
+    fn reference(program: &[u8], steps: usize, keys: &[u8]) -> Vec<TraceRecord> {
+        let mut chip8 = chip8(program);
+        for key in keys {
+            chip8.set_key(*key, true);
+        }
+        (0..steps).map(|_| {
+            let record = TraceRecord::new(&chip8, chip8.fetch().unwrap());
+            chip8.step().unwrap();
+            record
+        }).collect()
+    }
+
+    fn compare_with(program: &[u8], records: Vec<TraceRecord>, input: &str, ips: u32) -> Outcome {
+        let input = InputScript::parse(input).unwrap();
+        let budget = InstructionBudget::new(ips, FRAMES_PER_SECOND);
+        compare(&mut chip8(program), records.into_iter().map(Ok), &input, budget).unwrap()
+    }
+
+    fn diverged(outcome: Outcome) -> Divergence {
+        match outcome {
+            Outcome::Diverged(divergence) => *divergence,
+            outcome => panic!("expected a divergence, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn matches_its_own_trace() {
+        let outcome = compare_with(&PROGRAM, reference(&PROGRAM, 10, &[5]), "0 +5", 600);
+        assert!(matches!(outcome, Outcome::Matched { steps: 10 }), "{:?}", outcome);
+    }
+
+    #[test]
+    fn an_empty_reference_matches() {
+        assert!(matches!(compare_with(&PROGRAM, Vec::new(), "", 600), Outcome::Matched { steps: 0 }));
+    }
+
+    #[test]
+    fn reports_the_first_difference() {
+        let mut records = reference(&PROGRAM, 10, &[5]);
+        records[6].registers[2] = 0x7F;
+        records[6].register_i = 0x300;
+        records[8].pc = 0x400;
+        let divergence = diverged(compare_with(&PROGRAM, records.clone(), "0 +5", 600));
+        assert_eq!(divergence.step, 6);
+        assert_eq!(divergence.fields(), ["V2", "I"]);
+        assert_eq!(divergence.previous, Some(records[5].clone()));
+        assert_eq!(divergence.expected, records[6]);
+        assert!(divergence.report().contains("after 6 matching instructions"), "{}", divergence.report());
+    }
+
+    #[test]
+    fn input_is_applied_at_the_start_of_its_frame() {
+        // Without key 5 held, SKP V0 doesn't skip and the next PC differs
+        let divergence = diverged(compare_with(&PROGRAM, reference(&PROGRAM, 10, &[5]), "", 600));
+        assert_eq!((divergence.step, divergence.fields()), (2, vec![String::from("PC"), String::from("opcode")]));
+        // Pressed too late
+        let divergence = diverged(compare_with(&PROGRAM, reference(&PROGRAM, 10, &[5]), "1 +5", 600));
+        assert_eq!(divergence.step, 2);
+    }
+
+    #[test]
+    fn frames_follow_the_instruction_budget() {
+        let mut records = reference(&PROGRAM, 10, &[5]);
+        records[5].registers[2] = 0x7F;
+        // Two instructions a frame: steps 4 and 5 are frame 2
+        assert_eq!(diverged(compare_with(&PROGRAM, records.clone(), "0 +5", 120)).frame, 2);
+        // 1.5 a frame alternates 1 and 2: frames start at steps 0, 1, 3, 4 and 6
+        assert_eq!(diverged(compare_with(&PROGRAM, records, "0 +5", 90)).frame, 3);
+    }
+
+    #[test]
+    fn faults_while_the_reference_continues() {
+        // LD V0, 1, then an unknown opcode
+        let program = [0x60, 0x01, 0xFF, 0xFF];
+        let mut twin = chip8(&program);
+        let mut records = vec![TraceRecord::new(&twin, twin.fetch().unwrap())];
+        twin.step().unwrap();
+        records.push(TraceRecord::new(&twin, twin.fetch().unwrap()));
+        match compare_with(&program, records, "", 600) {
+            Outcome::Fault { step: 1, error: Chip8Error::UnknownOpcode { opcode: 0xFFFF, address: 0x202 } } => {},
+            outcome => panic!("expected a fault, got {:?}", outcome)
+        }
+    }
+
+    #[test]
+    fn bad_reference_records_are_errors() {
+        let records = reference(&PROGRAM, 2, &[5]).into_iter().map(Ok)
+            .chain([Err(String::from("line 3: missing SP"))]);
+        let input = InputScript::default();
+        let result = compare(&mut chip8(&PROGRAM), records, &input, InstructionBudget::new(600, FRAMES_PER_SECOND));
+        assert_eq!(result.unwrap_err(), "line 3: missing SP");
+    }
+
+    #[test]
+    fn parses_input_scripts() {
+        let script = InputScript::parse("# header\n\n120 +5 -a # comment\n  10 +F\n120 -5\n").unwrap();
+        assert_eq!(script.events, [(10, 0xF, true), (120, 5, true), (120, 0xA, false), (120, 5, false)]);
+        for (text, message) in [
+            ("x +5", "frame number"),
+            ("-1 +5", "frame number"),
+            ("10 5", "+KEY or -KEY"),
+            ("10 +", "hex key"),
+            ("10 +G", "hex key"),
+            ("10 +10", "hex key"),
+            ("10 +\u{20AC}", "hex key")
+        ] {
+            let error = InputScript::parse(text).unwrap_err();
+            assert!(error.contains(message) && error.starts_with("line 1:"), "'{}' gave '{}'", text, error);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::{io::{self, Write}, time::{Duration, Instant}};
+use crossterm::{cursor::{Hide, MoveTo, Show}, event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags}, queue, style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor}, terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen}};
+
+use crate::{audio::Sound, config::{DARK_COLOR, LIGHT_COLOR, PLANE_2_COLOR, PLANE_3_COLOR}, frontend::{Frontend, Input, SlotRequest}, screenshot::ImageFormat, terminal::{self as glyphs, Glyphs}};
+
+/// How long a key counts as held after the terminal reports it, when the terminal can't report releases.
+/// Long enough to bridge the gap before the terminal's key repeat starts.
+const KEY_HOLD: Duration = Duration::from_millis(300);
+
+/// How the terminal frontend looks. The defaults come from config.rs.
+#[derive(Clone, Debug)]
+pub struct TerminalOptions {
+    pub glyphs: Glyphs,
+    /// Color of pixels that are on
+    pub foreground: (u8, u8, u8),
+    /// Color of pixels that are off
+    pub background: (u8, u8, u8),
+    /// Color of pixels only on in XO-CHIP's second bitplane
+    pub plane_2: (u8, u8, u8),
+    /// Color of pixels on in both XO-CHIP bitplanes
+    pub plane_3: (u8, u8, u8)
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        TerminalOptions {
+            glyphs: Glyphs::HalfBlock,
+            foreground: DARK_COLOR,
+            background: LIGHT_COLOR,
+            plane_2: PLANE_2_COLOR,
+            plane_3: PLANE_3_COLOR
+        }
+    }
+}
+
+/// Draws the screen with Unicode characters in the terminal it was started from, reads the keypad from the
+/// same keys as the window and rings the bell for sound. The terminal is put back the way it was on drop.
+pub struct TerminalFrontend {
+    options: TerminalOptions,
+    // The terminal sends key releases, so keys are held until they come
+    reports_releases: bool,
+    keys: [KeyState; 16],
+    rewind: KeyState,
+    // The last frame drawn, so an unchanged screen isn't sent again
+    last_frame: Vec<Vec<u8>>,
+    message: String,
+    // Everything is redrawn on the next frame, after a resize or a new message
+    stale: bool,
+    sound_playing: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyState {
+    Up,
+    Down,
+    DownUntil(Instant)
+}
+
+impl KeyState {
+    fn is_down(&self, now: Instant) -> bool {
+        match self {
+            KeyState::Up => false,
+            KeyState::Down => true,
+            KeyState::DownUntil(until) => now < *until
+        }
+    }
+}
+
+impl TerminalFrontend {
+    /// Switches the terminal to raw mode and an alternate screen.
+    pub fn new(options: TerminalOptions) -> Result<Self, String> {
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+
+        let mut stdout = io::stdout();
+        let setup = queue!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))
+            .and_then(|_| match reports_releases {
+                true => queue!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)),
+                false => Ok(())
+            })
+            .and_then(|_| stdout.flush());
+
+        let frontend = TerminalFrontend {
+            options,
+            reports_releases,
+            keys: [KeyState::Up; 16],
+            rewind: KeyState::Up,
+            last_frame: Vec::new(),
+            message: String::new(),
+            stale: true,
+            sound_playing: false
+        };
+        // Dropping the frontend puts the terminal back
+        setup.map_err(|e| e.to_string())?;
+        Ok(frontend)
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, input: &mut Input) {
+        let state = match key.kind {
+            KeyEventKind::Release => KeyState::Up,
+            _ if self.reports_releases => KeyState::Down,
+            _ => KeyState::DownUntil(Instant::now() + KEY_HOLD)
+        };
+        let pressed = key.kind == KeyEventKind::Press;
+
+        match key.code {
+            // Raw mode turns off ^C, so it is handled here
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => { input.quit |= pressed; },
+            KeyCode::Char(_) if key.modifiers.contains(KeyModifiers::CONTROL) => {},
+            KeyCode::Char(c) => {
+                if let Some(key) = keypad_key(c.to_ascii_lowercase()) {
+                    self.keys[key] = state;
+                }
+            },
+            KeyCode::Backspace => { self.rewind = state; },
+            KeyCode::Esc if pressed => { input.quit = true; },
+            KeyCode::F(n @ 1..=9) if pressed => {
+                let request = if key.modifiers.contains(KeyModifiers::SHIFT) {
+                    SlotRequest::Save(n)
+                } else {
+                    SlotRequest::Load(n)
+                };
+                input.slot_requests.push(request);
+            },
+            KeyCode::F(10) if pressed => { input.toggle_gif = true; },
+            KeyCode::F(12) if pressed => {
+                let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+                input.screenshots.push(if shift { ImageFormat::Pbm } else { ImageFormat::Png });
+            },
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self, pixels: &[Vec<u8>]) -> io::Result<()> {
+        let colors = [self.options.background, self.options.foreground, self.options.plane_2, self.options.plane_3]
+            .map(|(r, g, b)| Color::Rgb { r, g, b });
+        let rows = glyphs::cells(pixels, self.options.glyphs);
+        let width = rows.first().map_or(0, Vec::len);
+
+        let mut out = io::stdout().lock();
+        if self.stale {
+            queue!(out, ResetColor, Clear(ClearType::All))?;
+        }
+
+        let (columns, lines) = terminal::size()?;
+        if (columns as usize) < width || (lines as usize) < rows.len() + 1 {
+            let message = format!("The terminal needs to be at least {}x{} to show the screen", width, rows.len() + 1);
+            queue!(out, MoveTo(0, 0), Print(message))?;
+            return out.flush();
+        }
+
+        let mut current = None;
+        for (y, row) in rows.iter().enumerate() {
+            queue!(out, MoveTo(0, y as u16))?;
+            for cell in row {
+                // Only send colors when they change, which is rarely on a two color screen
+                if current != Some((cell.foreground, cell.background)) {
+                    let (foreground, background) = (colors[cell.foreground as usize], colors[cell.background as usize]);
+                    queue!(out, SetForegroundColor(foreground), SetBackgroundColor(background))?;
+                    current = Some((cell.foreground, cell.background));
+                }
+                queue!(out, Print(cell.glyph))?;
+            }
+        }
+        queue!(out, ResetColor, MoveTo(0, rows.len() as u16), Clear(ClearType::CurrentLine), Print(&self.message))?;
+        out.flush()
+    }
+}
+
+impl Frontend for TerminalFrontend {
+    fn present(&mut self, pixels: &[Vec<u8>]) {
+        if !self.stale && self.last_frame == pixels {
+            return;
+        }
+        // A terminal that can't be written to has nobody looking at it, so there is nothing to report to
+        let _ = self.draw(pixels);
+        self.last_frame = pixels.to_vec();
+        self.stale = false;
+    }
+
+    fn play_sound(&mut self, sound: Option<Sound>) {
+        // The bell can't play a pitch or a pattern, only mark the start of each beep
+        if sound.is_some() && !self.sound_playing {
+            let mut out = io::stdout();
+            let _ = out.write_all(b"\x07").and_then(|_| out.flush());
+        }
+        self.sound_playing = sound.is_some();
+    }
+
+    fn poll_input(&mut self) -> Input {
+        let mut input = Input::default();
+
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            match event::read() {
+                Ok(Event::Key(key)) => self.handle_key(key, &mut input),
+                Ok(Event::Resize(..)) => { self.stale = true; },
+                Ok(_) => {},
+                Err(_) => break
+            }
+        }
+
+        let now = Instant::now();
+        for (key, state) in self.keys.iter().enumerate() {
+            input.keys[key] = state.is_down(now);
+        }
+        input.rewinding = self.rewind.is_down(now);
+        input
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = message.to_string();
+        self.stale = true;
+    }
+}
+
+impl Drop for TerminalFrontend {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.reports_releases {
+            let _ = queue!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(stdout, ResetColor, Show, LeaveAlternateScreen);
+        let _ = stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The same layout as the window: 1234/QWER/ASDF/ZXCV for 123C/456D/789E/A0BF.
+fn keypad_key(c: char) -> Option<usize> {
+    match c {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None
+    }
+}
@@ -0,0 +1,142 @@
+//! Runs test ROMs headless under each platform and compares the screen they end on with a golden copy in
+//! tests/golden, drawn the way `--headless` prints it.
+//!
+//! ROMs are either files in roms/ or sources in tests/roms, which are assembled first. After a deliberate
+//! change in behaviour, run `UPDATE_GOLDEN=1 cargo test --test golden` to rewrite the golden files, and
+//! review the diff.
+
+use std::{env, fs, path::PathBuf};
+
+use chip_8::{assembler, chip8::Chip8, platform::Platform, rng::{Rng, RngKind}, tracediff::InputScript};
+
+/// Where a test ROM comes from.
+enum Rom {
+    /// A ROM file, relative to the crate root
+    File(&'static str),
+    /// An assembly source in tests/roms
+    Source(&'static str)
+}
+
+struct Case {
+    /// Golden files are named `{name}-{platform}.txt`
+    name: &'static str,
+    rom: Rom,
+    frames: u64,
+    /// An input script in tests/roms, in the format tracediff takes
+    input: Option<&'static str>,
+    platforms: &'static [Platform]
+}
+
+impl Case {
+    fn rom(&self) -> Vec<u8> {
+        match self.rom {
+            Rom::File(path) => fs::read(path).unwrap_or_else(|e| panic!("could not read {}: {}", path, e)),
+            Rom::Source(name) => {
+                let path = format!("tests/roms/{}", name);
+                assembler::assemble_file(path.as_ref()).unwrap_or_else(|e| panic!("could not assemble {}: {}", path, e))
+            }
+        }
+    }
+
+    fn input(&self) -> InputScript {
+        let Some(name) = self.input else {
+            return InputScript::default();
+        };
+        let path = format!("tests/roms/{}", name);
+        let text = fs::read_to_string(&path).unwrap_or_else(|e| panic!("could not read {}: {}", path, e));
+        InputScript::parse(&text).unwrap_or_else(|e| panic!("{}: {}", path, e))
+    }
+
+    /// Runs the ROM under `platform` and returns the screen it ends on.
+    fn run(&self, platform: Platform) -> String {
+        let profile = platform.profile();
+        let mut chip8 = Chip8::from_profile(&profile);
+        chip8.load_bytes(&self.rom()).unwrap_or_else(|e| panic!("{}: {}", self.name, e));
+        // Same numbers every run, so programs using CXNN have a stable golden screen
+        chip8.set_rng(Rng::new(RngKind::Modern, 0));
+
+        let input = self.input();
+        let mut instructions = profile.instruction_budget();
+        for frame in 0..self.frames {
+            if chip8.has_exited() {
+                break;
+            }
+            input.apply(&mut chip8, frame);
+            if let Err(e) = chip8.run_frame(instructions.next_frame()) {
+                panic!("{} on {} faulted in frame {}: {}", self.name, platform, frame, e);
+            }
+        }
+        screen(&chip8)
+    }
+
+    /// Compares the screen under every platform with its golden file, or writes the golden files when
+    /// UPDATE_GOLDEN is set.
+    fn check(&self) {
+        let update = env::var_os("UPDATE_GOLDEN").is_some();
+        let mut failures = Vec::new();
+        for platform in self.platforms {
+            let path = PathBuf::from(format!("tests/golden/{}-{}.txt", self.name, platform));
+            let actual = self.run(*platform);
+            if update {
+                fs::write(&path, &actual).unwrap_or_else(|e| panic!("could not write {}: {}", path.display(), e));
+                continue;
+            }
+            let Ok(expected) = fs::read_to_string(&path) else {
+                failures.push(format!("{} is missing. Run with UPDATE_GOLDEN=1 to create it", path.display()));
+                continue;
+            };
+            if let Some(row) = expected.lines().zip(actual.lines()).position(|(a, b)| a != b).or_else(|| {
+                (expected.lines().count() != actual.lines().count()).then(|| expected.lines().count().min(actual.lines().count()))
+            }) {
+                failures.push(format!("{} on {} differs from {} from row {}:\n{}", self.name, platform, path.display(), row, actual));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+    }
+}
+
+/// `#` for pixels in the first plane, `+` for the second only and `@` for both, like `--headless` prints.
+fn screen(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    for row in chip8.pixels() {
+        out.extend(row.iter().map(|pixel| ['.', '#', '+', '@'][(*pixel & 0b11) as usize]));
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn ibm_logo() {
+    Case { name: "ibm_logo", rom: Rom::File("roms/IBM Logo.ch8"), frames: 60, input: None, platforms: &Platform::ALL }.check();
+}
+
+#[test]
+fn test_opcode() {
+    Case { name: "test_opcode", rom: Rom::File("roms/test_opcode.ch8"), frames: 60, input: None, platforms: &Platform::ALL }.check();
+}
+
+#[test]
+fn flags() {
+    Case { name: "flags", rom: Rom::Source("flags.asm"), frames: 120, input: None, platforms: &Platform::ALL }.check();
+}
+
+#[test]
+fn quirks() {
+    Case { name: "quirks", rom: Rom::Source("quirks.asm"), frames: 120, input: None, platforms: &Platform::ALL }.check();
+}
+
+#[test]
+fn keypad() {
+    Case { name: "keypad", rom: Rom::Source("keypad.asm"), frames: 60, input: Some("keypad.input"), platforms: &Platform::ALL }.check();
+}
+
+#[test]
+fn schip() {
+    let platforms = &[Platform::SuperChip, Platform::XoChip];
+    Case { name: "schip", rom: Rom::Source("schip.asm"), frames: 60, input: None, platforms }.check();
+}
+
+#[test]
+fn xochip() {
+    Case { name: "xochip", rom: Rom::Source("xochip.asm"), frames: 60, input: None, platforms: &[Platform::XoChip] }.check();
+}
@@ -0,0 +1,24 @@
+; Shared by the test ROMs, which show their results as rows of hex bytes so the golden screens pin them down.
+
+; Draws V0 as two hex digits at (VA, VB) and moves VA past them. Uses V1, VF and I.
+hex:
+    LD V1, V0
+    SHR V1
+    SHR V1
+    SHR V1
+    SHR V1
+    LD F, V1
+    DRW VA, VB, 5
+    ADD VA, 5
+    LD V1, 0x0F
+    AND V1, V0
+    LD F, V1
+    DRW VA, VB, 5
+    ADD VA, 6
+    RET
+
+; Moves to the start of the next row.
+newline:
+    LD VA, 1
+    ADD VB, 6
+    RET
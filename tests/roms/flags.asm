@@ -0,0 +1,68 @@
+; The result and VF of each arithmetic instruction, two instructions to a row:
+;   8XY4 without and with carry, 8XY5 without and with borrow, 8XY7 without and with borrow,
+;   8XY6 and 8XYE shifting a bit out, 8XY4 and 8XY5 with VF as the destination
+    LD VA, 1
+    LD VB, 1
+
+    LD V2, 0x10
+    LD V3, 0x20
+    ADD V2, V3
+    CALL show
+    LD V2, 0xF0
+    LD V3, 0x20
+    ADD V2, V3
+    CALL show
+    CALL newline
+
+    LD V2, 0x30
+    LD V3, 0x10
+    SUB V2, V3
+    CALL show
+    LD V2, 0x10
+    LD V3, 0x30
+    SUB V2, V3
+    CALL show
+    CALL newline
+
+    LD V2, 0x10
+    LD V3, 0x30
+    SUBN V2, V3
+    CALL show
+    LD V2, 0x30
+    LD V3, 0x10
+    SUBN V2, V3
+    CALL show
+    CALL newline
+
+    LD V2, 0x05
+    SHR V2
+    CALL show
+    LD V2, 0x81
+    SHL V2
+    CALL show
+    CALL newline
+
+    LD VF, 0xF0
+    LD V3, 0x20
+    ADD VF, V3
+    LD V2, VF
+    CALL show
+    LD VF, 0x10
+    LD V3, 0x30
+    SUB VF, V3
+    LD V2, VF
+    CALL show
+
+end:
+    JP end
+
+; Shows V2 and VF
+show:
+    LD V4, VF
+    LD V0, V2
+    CALL hex
+    LD V0, V4
+    CALL hex
+    RET
+
+:include "common.asm"
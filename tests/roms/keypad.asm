@@ -0,0 +1,29 @@
+; Driven by tests/roms/keypad.input. Shows, in order: the key FX0A waited for, 01 once EXA1 sees it released,
+; 05 once EX9E sees key 5 held, and the key FX0A returns straight away while 5 is still held.
+    LD VA, 1
+    LD VB, 1
+
+    LD V0, K
+    CALL hex
+
+    LD V2, 7
+released:
+    SKNP V2
+    JP released
+    LD V0, 0x01
+    CALL hex
+
+    LD V2, 5
+held:
+    SKP V2
+    JP held
+    LD V0, V2
+    CALL hex
+
+    LD V0, K
+    CALL hex
+
+end:
+    JP end
+
+:include "common.asm"
@@ -0,0 +1,84 @@
+; One byte per quirk, so each platform's golden screen shows how it behaves:
+;   jump_uses_vx, vf_reset, shift_uses_vy, then VF after that shift
+;   index_increments, index_overflow_sets_vf, wrap_sprites, then the number of sprites drawn in one frame,
+;   which display_wait keeps to 1
+    LD VA, 1
+    LD VB, 1
+
+    ; BNNN lands on the first or second LD depending on which register it adds
+    LD V0, 0
+    LD V2, 4
+    JP V0, jump_target
+jump_target:
+    LD V0, 0x01
+    JP jumped
+    LD V0, 0x02
+jumped:
+    CALL hex
+
+    LD VF, 0x05
+    LD V2, 0x01
+    LD V3, 0x02
+    OR V2, V3
+    LD V0, VF
+    CALL hex
+
+    ; Bit 0 differs between V2 and V3, so VF shows which value was shifted
+    LD V2, 0x01
+    LD V3, 0x10
+    SHR V2, V3
+    LD V4, VF
+    LD V0, V2
+    CALL hex
+    LD V0, V4
+    CALL hex
+    CALL newline
+
+    ; Loading twice gives the second byte if I moved on after the first load
+    LD I, scratch
+    LD V0, [I]
+    LD V0, [I]
+    CALL hex
+
+    LD VF, 0
+    LD I, 0xFFF
+    LD V2, 1
+    ADD I, V2
+    LD V0, VF
+    CALL hex
+
+    ; A sprite hanging off the right edge collides with one at the left edge only if it wrapped
+    LD I, dot_row
+    LD V2, 60
+    LD V3, 31
+    DRW V2, V3, 1
+    LD V2, 0
+    DRW V2, V3, 1
+    LD V0, VF
+    CALL hex
+
+    ; Count empty sprites drawn until the delay timer runs out
+    LD I, blank
+    LD V2, 0
+    LD V3, 1
+    LD DT, V3
+count:
+    DRW V2, V2, 1
+    ADD V2, 1
+    LD V3, DT
+    SE V3, 0
+    JP count
+    LD V0, V2
+    CALL hex
+
+end:
+    JP end
+
+scratch:
+    DB 0xAA, 0xBB, 0xCC
+dot_row:
+    DB 0xFF
+blank:
+    DB 0x00
+
+:include "common.asm"
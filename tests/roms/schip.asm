@@ -0,0 +1,47 @@
+; SUPER-CHIP: a large font 7 in high resolution scrolled right and down, and a 16x16 box clipped at the
+; bottom whose collision rows end up in V5 and V6, which are saved to and loaded back from the RPL flags.
+    HIGH
+    LD V0, 0x07
+    LD HF, V0
+    LD V1, 0x02
+    LD V2, 0x02
+    DRW V1, V2, 10
+    LD I, sprite
+    LD V1, 0x70
+    LD V2, 0x38
+    DRW V1, V2, 0
+    LD V5, VF
+    DRW V1, V2, 0
+    LD V6, VF
+    SCR
+    SCD 1
+    LD R, V6
+    LD V0, 0
+    LD V6, 0
+    LD V6, R
+    LD VA, 1
+    LD VB, 20
+    LD V0, V5
+    CALL hex
+    LD V0, V6
+    CALL hex
+    EXIT
+sprite:
+    DW 0xFFFF
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0x8001
+    DW 0xFFFF
+
+:include "common.asm"
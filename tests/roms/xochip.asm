@@ -0,0 +1,40 @@
+; XO-CHIP: a sprite drawn to both bitplanes from a long index, the second plane scrolled up on its own, a skip
+; over F000 NNNN, and a register range saved in reverse and loaded back in order.
+    PLANE 3
+    LD I, LONG sprite
+    LD V0, 1
+    LD V1, 1
+    DRW V0, V1, 2
+    LD V0, 0xFF
+    SE V0, 0xFF
+    LD I, LONG sprite
+    LD V2, 5
+    PLANE 2
+    SCU 1
+    LD I, 0x300
+    LD V3, 7
+    SAVE V3, V0
+    LD V0, 0
+    LD V3, 0
+    LOAD V0, V3
+    LD V4, V0
+    LD V5, V1
+    LD V6, V2
+    LD V7, V3
+    PLANE 1
+    LD VA, 1
+    LD VB, 8
+    LD V0, V4
+    CALL hex
+    LD V0, V5
+    CALL hex
+    LD V0, V6
+    CALL hex
+    LD V0, V7
+    CALL hex
+    EXIT
+sprite:
+    DW 0xF0F0
+    DW 0xFF00
+
+:include "common.asm"